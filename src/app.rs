@@ -1,16 +1,20 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
-use crate::chess::STAR_VALUE;
 
 // hide console window on Windows in release
-use super::chess::{self, LiBoard, MovePiece};
+use super::chess::{self, BoardOptions, Cell, Color, CountLimits, Difficulty, LiBoard, MoveError, MovePiece, Piece, Square};
 use super::egui_widgets::progress_bar::ProgressBar;
+use super::notation::NotationError;
+use super::pack::{PackProgress, PuzzlePack};
+use super::replay::Replay;
+use super::solver::{SolveTask, SolverStats, SolverStrategy};
+use super::stats::{self, RoundHistory, RoundRecord, Summary};
 use eframe::{
-    egui::{self, Sense, TextBuffer, TextureOptions, Ui},
+    egui::{self, Sense, TextureOptions, Ui},
     emath::{Pos2, Rect},
     epaint::{Color32, TextureHandle},
 };
-use egui::{Button, Painter, PointerButton, RichText, Stroke, Vec2};
-use rpds::Vector;
+use egui::{Button, Key, Modifiers, Painter, PointerButton, RichText, Stroke, Vec2};
+use serde::{Deserialize, Serialize};
 use std::thread;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::{collections::HashMap, time::Duration};
@@ -19,14 +23,40 @@ use std::{collections::HashMap, time::Duration};
 // egui has dragging implemented already !
 pub struct MyApp {
     // pictures and animations
-    textures: HashMap<i8, Option<egui::TextureHandle>>, // piece -> texture mapping
+    textures: HashMap<Cell, Option<egui::TextureHandle>>, // piece -> texture mapping
     // game state
     arrows_to_draw: Vec<ArrowMove>,
     board: LiBoard,
+    start_board: LiBoard,                         // the board as the round began
+    round_started: Option<f64>,                   // egui time the round was first shown
+    seed: Option<u64>,                            // the current board is set up from this and the side panel settings, None for pasted puzzles
+    seed_text: String,                            // seed typed into the side panel
+    puzzle_text: String,                          // puzzle notation to copy or paste
+    puzzle_error: Option<NotationError>,          // why the last paste failed
+    packs: Vec<PuzzlePack>,                       // bundled and loaded puzzle packs
+    active_pack: Option<(usize, usize)>,          // pack and puzzle being played, None for generated boards
+    pack_progress: HashMap<String, PackProgress>, // by pack title
+    #[cfg(not(target_arch = "wasm32"))]
+    pack_path: String, // pack file typed into the side panel
+    pack_error: Option<String>,                   // why the last pack didn't load
     cur_move_cnt: i8,
-    optimal_move_cnt: i8,
-    choice_piece: i8,
-    star_cnt: i8,
+    undo_stack: Vec<(LiBoard, i8)>, // the board and move count before each move this round
+    redo_stack: Vec<(LiBoard, i8)>, // moves taken back, latest last
+    undone: bool,                   // a move was taken back this round
+    attempt: u32,                   // tries at the current board, starting at 1
+    optimal_move_cnt: Option<i8>,   // None if the board can't be solved
+    choice_piece: Piece,
+    extra_pieces: Vec<Piece>, // pieces the player gets on top of choice_piece
+    promotion_piece: Piece,
+    board_size: usize, // width and height of the next board
+    difficulty: Difficulty,
+    round_difficulty: Option<Difficulty>, // what the round's board was generated for, None for pasted and pack puzzles
+    enemy_cnt: i8,
+    obstacle_cnt: i8,
+    solver_strategy: SolverStrategy,
+    solver_stats: SolverStats,            // from the last solve
+    solve_task: Option<SolveTask>,        // Some while the optimal solution is being computed
+    move_error: Option<(MoveError, f64)>, // why the last drop was rejected and when
     board_light_sq_color: Color32,
     board_dark_sq_color: Color32,
     window_bg_color: Color32,
@@ -35,8 +65,11 @@ pub struct MyApp {
     auto_play: bool,
     in_game: bool,
     show_side_panel: bool,
-    solution_path: chess::SolutionPath,
+    solution_paths: Vec<chess::SolutionPath>, // some of the optimal routes
+    route_cnt: u64,                           // how many optimal routes there are in total
     show_solution: bool,
+    shown_route: usize,     // index into solution_paths
+    replay: Option<Replay>, // Some while a route is being replayed in place of the board
     // timer things
     timed: bool, // see how many rounds you can complete in X minutes
     starting_timer: u64,
@@ -47,6 +80,10 @@ pub struct MyApp {
     // stats
     points: u64,
     streak: u64,
+    history: RoundHistory,
+    session_start: usize, // rounds before this one in history were played in earlier runs
+    newer_save: bool,     // the save is from a newer version, so nothing gets saved over it
+    show_stats: bool,
     // ui sizing
     board_width: Option<f32>,
     // Manual click drag tracking. egui doesn't support figuring out what button a widget was released by.
@@ -54,30 +91,22 @@ pub struct MyApp {
     primary_clicked: bool,
 }
 
-// Captures drawing an arrow from (start_i, start_j) to (end_i, end_j).
+// Captures drawing an arrow from start to end.
 // Arrows are drawn by right click.
 #[derive(Debug, Clone)]
 struct ArrowMove {
-    start_i: usize,
-    start_j: usize,
-    end_i: usize,
-    end_j: usize,
+    start: Square,
+    end: Square,
 }
 
 impl From<MovePiece> for ArrowMove {
     fn from(m: MovePiece) -> Self {
-        let MovePiece { i, j, goal_i, goal_j } = m;
-        Self {
-            start_i: i,
-            start_j: j,
-            end_i: goal_i,
-            end_j: goal_j,
-        }
+        Self { start: m.from, end: m.to }
     }
 }
 
 enum PieceStates {
-    Dragged(Rect, i8),             // where to draw image and what image to draw
+    Dragged(Rect, Cell, Square),   // where to draw image, what image to draw and where the piece came from
     ArrowDragged(ArrowMove),       // where to draw arrows as it's being dragged
     DragReleased(Rect, MovePiece), // draw the image just before releasing
     ArrowDragReleased(ArrowMove),  // where to draw arrows
@@ -85,63 +114,449 @@ enum PieceStates {
 }
 
 impl MyApp {
-    fn draw_arrow(&self, arrow_move: ArrowMove, painter: &Painter, size: f32, board_rect: Rect) {
-        let ArrowMove { start_i, start_j, end_i, end_j } = arrow_move;
-        let start_x = (start_j as i8) as f32 * size + board_rect.min.x + size / 2.0;
-        let start_y = (start_i as i8) as f32 * size + board_rect.min.y + size / 2.0;
+    // picks up the settings and progress saved by the last run
+    pub fn new(cc: &eframe::CreationContext) -> Self {
+        Self::load(cc.storage)
+    }
+
+    fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        let saved = storage.and_then(|storage| eframe::get_value::<SavedState>(storage, eframe::APP_KEY));
+        // a save from a newer version might not mean what this one expects, so it's left alone and not written over
+        let newer_save = saved.as_ref().map_or(false, |saved| saved.version > SAVE_VERSION);
+        let mut app = Self::from_saved(saved.filter(|_| !newer_save).map(SavedState::migrated).unwrap_or_default());
+        app.newer_save = newer_save;
+        app
+    }
+
+    fn saved_state(&self) -> SavedState {
+        SavedState {
+            version: SAVE_VERSION,
+            points: self.points,
+            streak: self.streak,
+            pack_progress: self.pack_progress.clone(),
+            history: self.history.clone(),
+            choice_piece: self.choice_piece,
+            extra_pieces: self.extra_pieces.clone(),
+            promotion_piece: self.promotion_piece,
+            board_size: self.board_size,
+            difficulty: self.difficulty,
+            enemy_cnt: self.enemy_cnt,
+            obstacle_cnt: self.obstacle_cnt,
+            board_light_sq_color: self.board_light_sq_color,
+            board_dark_sq_color: self.board_dark_sq_color,
+            window_bg_color: self.window_bg_color,
+            arrow_color: self.arrow_color,
+            side_panel_dark_mode: self.side_panel_dark_mode,
+            starting_timer: self.starting_timer,
+        }
+    }
+
+    // Board for the next round. In a pack that's the next puzzle once the current one is solved,
+    // otherwise a board for the current side panel settings and a fresh seed.
+    fn new_board(&mut self) -> LiBoard {
+        if let Some((pack_idx, puzzle_idx)) = self.active_pack {
+            let pack = &self.packs[pack_idx];
+            let progress = self.pack_progress.entry(pack.title.clone()).or_insert_with(|| PackProgress::new(pack.puzzles.len()));
+            let next = if progress.is_solved(puzzle_idx) {
+                progress.next_puzzle(puzzle_idx, pack.puzzles.len())
+            } else {
+                puzzle_idx
+            };
+            self.active_pack = Some((pack_idx, next));
+            self.seed = None;
+            return pack.puzzles[next].board.clone();
+        }
+        let seed = rand::random();
+        self.seed = Some(seed);
+        self.seed_text = seed.to_string();
+        self.seeded_board(seed)
+    }
+
+    // board the seed gives with the current side panel settings
+    fn seeded_board(&self, seed: u64) -> LiBoard {
+        LiBoard::from_options_seeded(
+            &BoardOptions {
+                size: self.board_size,
+                star_cnt: self.difficulty.star_cnt(),
+                choice_piece: self.choice_piece,
+                extra_pieces: self.extra_pieces.clone(),
+                promotion_piece: self.promotion_piece,
+                enemy_cnt: self.enemy_cnt,
+                obstacle_cnt: self.obstacle_cnt,
+                target: Some(self.difficulty.target()),
+            },
+            seed,
+        )
+    }
+
+    // Start a round on a board and solve it in the background.
+    // Getting the same board again, from the same pack puzzle or seed and settings, carries on its attempt count.
+    fn start_round(&mut self, board: LiBoard) {
+        if board != self.start_board {
+            self.attempt = 0;
+        }
+        self.start_board = board;
+        // only generated boards have a seed
+        self.round_difficulty = self.seed.map(|_| self.difficulty);
+        self.retry_round();
+        self.solve_board();
+    }
+
+    // Play the round's board again from the start. It's already been solved, so the optimal count and routes are kept.
+    fn retry_round(&mut self) {
+        self.in_game = true;
+        self.board = self.start_board.clone();
+        self.round_started = None;
+        self.cur_move_cnt = 0;
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.undone = false;
+        self.attempt += 1;
+        self.replay = None;
+        self.arrows_to_draw.clear();
+    }
+
+    // plays a move that's already been validated, collecting the star on its square
+    fn play_move(&mut self, m: &MovePiece) {
+        if self.in_game {
+            self.undo_stack.push((self.board.clone(), self.cur_move_cnt));
+            self.redo_stack.clear();
+        }
+        if self.board[m.to] == Cell::Star {
+            play_sound("capture");
+            self.board.num_star_cnt -= 1;
+        } else {
+            play_sound("move");
+        }
+        self.board.update_board(m);
+        if self.in_game {
+            self.cur_move_cnt += 1;
+        }
+        if self.board.num_star_cnt == 0 && self.in_game {
+            play_sound("win");
+        }
+    }
+
+    // take back the last move of the round, stars and all
+    fn undo_move(&mut self) {
+        // the replay board is on screen instead
+        if !self.in_game || self.replay.is_some() {
+            return;
+        }
+        if let Some((board, move_cnt)) = self.undo_stack.pop() {
+            self.redo_stack.push((std::mem::replace(&mut self.board, board), self.cur_move_cnt));
+            self.cur_move_cnt = move_cnt;
+            self.undone = true;
+            self.move_error = None;
+        }
+    }
 
-        let x = (end_j as i8) as f32 * size + board_rect.min.x + size / 2.0 - start_x;
-        let y = (end_i as i8) as f32 * size + board_rect.min.y + size / 2.0 - start_y;
+    fn redo_move(&mut self) {
+        if !self.in_game || self.replay.is_some() {
+            return;
+        }
+        if let Some((board, move_cnt)) = self.redo_stack.pop() {
+            // sounds the same as playing the move did
+            play_sound(if board.num_star_cnt < self.board.num_star_cnt { "capture" } else { "move" });
+            if board.num_star_cnt == 0 {
+                play_sound("win");
+            }
+            self.undo_stack.push((std::mem::replace(&mut self.board, board), self.cur_move_cnt));
+            self.cur_move_cnt = move_cnt;
+        }
+    }
+
+    // Buttons to start a replay of the shown route from the round's first board, then to play, pause and step through it
+    fn replay_controls(&mut self, ui: &mut Ui, now: f64) {
+        let Some(replay) = &mut self.replay else {
+            let path = self.solution_paths.get(self.shown_route);
+            if ui
+                .add_enabled(path.is_some(), Button::new("Replay Solution"))
+                .on_hover_text("Play an optimal route one move at a time")
+                .clicked()
+            {
+                self.replay = Some(Replay::new(&self.start_board, path.unwrap().clone()));
+            }
+            return;
+        };
+        ui.label(format!("Replay: move {} of {}", replay.step(), replay.move_cnt()));
+        let mut close = false;
+        ui.horizontal(|ui| {
+            if ui.add_enabled(replay.step() > 0, Button::new("Back")).clicked() {
+                replay.playing = false;
+                replay.seek(replay.step() - 1);
+            }
+            if ui.button(if replay.playing { "Pause" } else { "Play" }).clicked() {
+                // playing from the end starts over
+                if replay.is_done() {
+                    replay.seek(0);
+                }
+                replay.playing ^= true;
+            }
+            if ui.add_enabled(!replay.is_done(), Button::new("Step")).clicked() {
+                replay.playing = false;
+                if let Some(star) = replay.step_forward(now) {
+                    play_sound(if star { "capture" } else { "move" });
+                }
+            }
+            close = ui.button("Close").clicked();
+        });
+        if close {
+            self.replay = None;
+        }
+    }
+
+    // play a puzzle from one of the packs
+    fn start_pack_puzzle(&mut self, pack_idx: usize, puzzle_idx: usize) {
+        self.active_pack = Some((pack_idx, puzzle_idx));
+        self.seed = None;
+        self.start_round(self.packs[pack_idx].puzzles[puzzle_idx].board.clone());
+    }
+
+    // start solving the current board in the background. Replacing the task cancels any solve still running.
+    fn solve_board(&mut self) {
+        self.optimal_move_cnt = None;
+        self.solution_paths.clear();
+        self.route_cnt = 0;
+        self.shown_route = 0;
+        self.solve_task = Some(SolveTask::spawn(&self.board, self.solver_strategy));
+    }
+
+    // store the optimal move count and routes once the background solve finishes
+    fn poll_solve(&mut self) {
+        if let Some((routes, stats)) = self.solve_task.as_mut().and_then(|task| task.poll()) {
+            self.solve_task = None;
+            self.solver_stats = stats;
+            if let Some(routes) = routes {
+                self.optimal_move_cnt = Some(routes.move_cnt);
+                self.route_cnt = routes.count;
+                self.solution_paths = routes.paths;
+            }
+        }
+    }
+
+    fn draw_arrow(&self, arrow_move: ArrowMove, painter: &Painter, size: f32, board_rect: Rect) {
+        let ArrowMove { start, end } = arrow_move;
+        let (start_x, start_y, x, y) = arrow_coords(start, end, size, board_rect);
         arrow(painter, Pos2::new(start_x, start_y), Vec2::new(x, y), Stroke::new(size / 5.0, self.arrow_color));
     }
+
+    // a replay arrow with its move number halfway along
+    fn draw_numbered_arrow(&self, arrow_move: ArrowMove, number: usize, painter: &Painter, size: f32, board_rect: Rect) {
+        let (start_x, start_y, x, y) = arrow_coords(arrow_move.start, arrow_move.end, size, board_rect);
+        self.draw_arrow(arrow_move, painter, size, board_rect);
+        let middle = Pos2::new(start_x + x / 2.0, start_y + y / 2.0);
+        painter.circle_filled(middle, size / 6.0, Color32::from_gray(30));
+        painter.text(middle, egui::Align2::CENTER_CENTER, number, egui::FontId::proportional(size / 4.0), Color32::WHITE);
+    }
+}
+
+// where an arrow between the centres of two squares starts, and how far it goes
+fn arrow_coords(start: Square, end: Square, size: f32, board_rect: Rect) -> (f32, f32, f32, f32) {
+    let start_x = (start.j as i8) as f32 * size + board_rect.min.x + size / 2.0;
+    let start_y = (start.i as i8) as f32 * size + board_rect.min.y + size / 2.0;
+
+    let x = (end.j as i8) as f32 * size + board_rect.min.x + size / 2.0 - start_x;
+    let y = (end.i as i8) as f32 * size + board_rect.min.y + size / 2.0 - start_y;
+    (start_x, start_y, x, y)
 }
 
 impl Default for MyApp {
     fn default() -> Self {
-        let b = chess::LiBoard::new(5, chess::QUEEN_WHITE);
-        let (opt_cnt, path) = b.num_optimal_moves_to_star();
-        Self {
+        Self::from_saved(SavedState::default())
+    }
+}
+
+impl MyApp {
+    // the app with saved settings and progress, starting on a board for those settings
+    fn from_saved(saved: SavedState) -> Self {
+        let saved = saved.validated();
+        let mut app = Self {
             textures: HashMap::new(),
-            board: b.clone(),
+            // replaced by the first round's board below
+            board: LiBoard::default(),
+            start_board: LiBoard::default(),
+            round_started: None,
+            seed: None,
+            seed_text: String::new(),
+            puzzle_text: String::new(),
+            puzzle_error: None,
+            packs: PuzzlePack::bundled(),
+            active_pack: None,
+            pack_progress: saved.pack_progress,
+            #[cfg(not(target_arch = "wasm32"))]
+            pack_path: String::new(),
+            pack_error: None,
             show_side_panel: true,
-            optimal_move_cnt: opt_cnt,
-            solution_path: path,
+            optimal_move_cnt: None,
+            solution_paths: Vec::new(),
+            route_cnt: 0,
             arrows_to_draw: Vec::new(),
             cur_move_cnt: 0,
-            choice_piece: chess::QUEEN_WHITE,
-            star_cnt: 5,
-            board_light_sq_color: Color32::from_rgba_premultiplied(213, 213, 213, 170),
-            board_dark_sq_color: Color32::BLACK,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            undone: false,
+            attempt: 1,
+            choice_piece: saved.choice_piece,
+            extra_pieces: saved.extra_pieces,
+            promotion_piece: saved.promotion_piece,
+            board_size: saved.board_size,
+            difficulty: saved.difficulty,
+            round_difficulty: None,
+            enemy_cnt: saved.enemy_cnt,
+            obstacle_cnt: saved.obstacle_cnt,
+            solver_strategy: SolverStrategy::default(),
+            solver_stats: SolverStats::default(),
+            solve_task: None,
+            move_error: None,
+            board_light_sq_color: saved.board_light_sq_color,
+            board_dark_sq_color: saved.board_dark_sq_color,
             auto_play: false,
-            window_bg_color: Color32::BLACK,
-            arrow_color: Color32::from_rgba_premultiplied(81, 171, 0, 104),
-            side_panel_dark_mode: false,
+            window_bg_color: saved.window_bg_color,
+            arrow_color: saved.arrow_color,
+            side_panel_dark_mode: saved.side_panel_dark_mode,
             show_solution: false,
+            shown_route: 0,
+            replay: None,
             // timers
             timed: false,
             timer: 0,
             in_timed_round: false,
             cur_timed_num_wins: 0,
             last_timed_game: None,
-            starting_timer: 2000,
-            streak: 0,
-            points: 0,
+            starting_timer: saved.starting_timer,
+            streak: saved.streak,
+            points: saved.points,
+            session_start: saved.history.rounds.len(),
+            newer_save: false,
+            history: saved.history,
+            show_stats: false,
             in_game: true,
             board_width: None,
             secondary_clicked: false,
             primary_clicked: false,
+        };
+        let board = app.new_board();
+        app.start_round(board);
+        app
+    }
+}
+
+// Bump when a saved field changes meaning, and migrate the older versions in SavedState::migrated.
+// Fields added later only need a default.
+const SAVE_VERSION: u32 = 1;
+
+// Settings and progress kept between runs, in eframe's storage
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default)]
+struct SavedState {
+    version: u32,
+    points: u64,
+    streak: u64,
+    pack_progress: HashMap<String, PackProgress>,
+    history: RoundHistory,
+    choice_piece: Piece,
+    extra_pieces: Vec<Piece>,
+    promotion_piece: Piece,
+    board_size: usize,
+    difficulty: Difficulty,
+    enemy_cnt: i8,
+    obstacle_cnt: i8,
+    board_light_sq_color: Color32,
+    board_dark_sq_color: Color32,
+    window_bg_color: Color32,
+    arrow_color: Color32,
+    side_panel_dark_mode: bool,
+    starting_timer: u64,
+}
+
+// what a first run starts with
+impl Default for SavedState {
+    fn default() -> Self {
+        Self {
+            version: SAVE_VERSION,
+            points: 0,
+            streak: 0,
+            pack_progress: HashMap::new(),
+            history: RoundHistory::default(),
+            choice_piece: Piece::Queen,
+            extra_pieces: Vec::new(),
+            promotion_piece: Piece::Queen,
+            board_size: 8,
+            difficulty: Difficulty::Medium,
+            enemy_cnt: 0,
+            obstacle_cnt: 0,
+            board_light_sq_color: Color32::from_rgba_premultiplied(213, 213, 213, 170),
+            board_dark_sq_color: Color32::BLACK,
+            window_bg_color: Color32::BLACK,
+            arrow_color: Color32::from_rgba_premultiplied(81, 171, 0, 104),
+            side_panel_dark_mode: false,
+            starting_timer: 2000,
+        }
+    }
+}
+
+impl SavedState {
+    // brings a save from an older version up to this one
+    fn migrated(mut self) -> SavedState {
+        match self.version {
+            // the first version, read as it is
+            1 => (),
+            // no version before the first was ever written, so there's nothing to go on
+            _ => return SavedState::default(),
+        }
+        self.version = SAVE_VERSION;
+        self
+    }
+
+    // The save file can be edited or come from a buggy build, and the solver panics on some settings,
+    // so anything the side panel couldn't have picked goes back to its default.
+    fn validated(mut self) -> SavedState {
+        let default = SavedState::default();
+        if !chess::BOARD_SIZES.contains(&self.board_size) {
+            self.board_size = default.board_size;
+        }
+        if !matches!(self.promotion_piece, Piece::Queen | Piece::Knight | Piece::Rook | Piece::Bishop) {
+            self.promotion_piece = default.promotion_piece;
+        }
+        // each extra piece at most once, and only as many as the solver tracks
+        let mut extra_pieces = Vec::new();
+        for piece in self.extra_pieces {
+            if piece != Piece::Pawn && !extra_pieces.contains(&piece) {
+                extra_pieces.push(piece);
+            }
+        }
+        extra_pieces.truncate(chess::MAX_PLAYER_PIECES - 1);
+        self.extra_pieces = extra_pieces;
+        let limits = CountLimits::for_size(self.board_size);
+        self.enemy_cnt = self.enemy_cnt.clamp(0, limits.enemies);
+        self.obstacle_cnt = self.obstacle_cnt.clamp(0, limits.obstacles);
+        if self.starting_timer == 0 {
+            self.starting_timer = default.starting_timer;
         }
+        self
     }
 }
 
+// how long a rejected move message stays up
+const MOVE_ERROR_SECS: f64 = 2.0;
+
 // piece IMAGES
-static IMAGES: [&[u8]; 6] = [
+static IMAGES: [&[u8]; 14] = [
     include_bytes!("../images/star.png").as_slice(),
     include_bytes!("../images/icon.png").as_slice(),
     include_bytes!("../images/white_rook.png").as_slice(),
     include_bytes!("../images/white_knight.png").as_slice(),
     include_bytes!("../images/white_queen.png").as_slice(),
-    include_bytes!("../images/fire.png").as_slice(),
+    include_bytes!("../images/white_king.png").as_slice(),
+    include_bytes!("../images/white_pawn.png").as_slice(),
+    include_bytes!("../images/white_bishop.png").as_slice(),
+    include_bytes!("../images/black_pawn.png").as_slice(),
+    include_bytes!("../images/black_knight.png").as_slice(),
+    include_bytes!("../images/black_bishop.png").as_slice(),
+    include_bytes!("../images/black_rook.png").as_slice(),
+    include_bytes!("../images/black_queen.png").as_slice(),
+    include_bytes!("../images/black_king.png").as_slice(),
 ];
 
 // piece AUDIO
@@ -151,12 +566,21 @@ static AUDIO: [&[u8]; 3] = [
     include_bytes!("../sounds/capture.wav").as_slice(),
 ];
 
-fn img_id_map(i: i8) -> usize {
-    match i {
-        chess::QUEEN_WHITE => 4,
-        chess::KNIGHT_WHITE => 3,
-        chess::ROOK_WHITE => 2,
-        _ => panic!("invalid Image request"),
+// index into IMAGES for a piece
+fn img_id_map(color: Color, piece: Piece) -> usize {
+    match (color, piece) {
+        (Color::White, Piece::Queen) => 4,
+        (Color::White, Piece::Knight) => 3,
+        (Color::White, Piece::Rook) => 2,
+        (Color::White, Piece::King) => 5,
+        (Color::White, Piece::Pawn) => 6,
+        (Color::White, Piece::Bishop) => 7,
+        (Color::Black, Piece::Pawn) => 8,
+        (Color::Black, Piece::Knight) => 9,
+        (Color::Black, Piece::Bishop) => 10,
+        (Color::Black, Piece::Rook) => 11,
+        (Color::Black, Piece::Queen) => 12,
+        (Color::Black, Piece::King) => 13,
     }
 }
 
@@ -169,7 +593,7 @@ fn play_sound(name: &'static str) {
     #[cfg(target_arch = "wasm32")]
     {
         use web_sys::HtmlAudioElement;
-        let sample = match name.as_str() {
+        let sample = match name {
             "move" => "move.wav",
             "win" => "win.wav",
             "capture" => "capture.wav",
@@ -182,14 +606,16 @@ fn play_sound(name: &'static str) {
     thread::spawn(move || {
         use rodio::{source::Source, Decoder, OutputStream};
         use std::io::Cursor;
-        let sample = match name.as_str() {
+        let sample = match name {
             "move" => AUDIO[0],
             "win" => AUDIO[1],
             "capture" => AUDIO[2],
             _ => panic!("wrong type of sound?"),
         };
-        // Get a output stream handle to the default physical sound device
-        let (_stream, stream_handle) = OutputStream::try_default().unwrap();
+        // Get a output stream handle to the default physical sound device, there's nothing to play on without one
+        let Ok((_stream, stream_handle)) = OutputStream::try_default() else {
+            return;
+        };
         // using cursor to load it in memory
         let file = Cursor::new(sample);
         // Decode that sound file into a source
@@ -209,34 +635,74 @@ pub fn load_image(img: &[u8]) -> Result<egui::ColorImage, image::ImageError> {
     Ok(egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice()))
 }
 
-fn get_texture<'a>(app: &'a mut MyApp, ui: &'a mut Ui, img_id: i8) -> &'a TextureHandle {
+// Only stars and pieces have textures
+fn get_texture<'a>(app: &'a mut MyApp, ui: &'a mut Ui, cell: Cell) -> &'a TextureHandle {
     // where to draw currently dragged image
     // insert id if it isn't there
-    app.textures.entry(img_id).or_insert(None);
-
-    app.textures.get_mut(&img_id).unwrap().get_or_insert_with(|| {
-        let img;
-        let name;
-        if img_id == chess::STAR_VALUE {
-            // load star
-            img = load_image(IMAGES[0]).unwrap();
-            name = "star_img";
-        } else if img_id == 24 {
-            // TODO remove magic nums
-            img = load_image(IMAGES[5]).unwrap();
-            name = "fire";
-        } else {
-            img = load_image(IMAGES[img_id_map(img_id)]).unwrap();
-            name = "others"; // TODO fix
-        }
+    app.textures.entry(cell).or_insert(None);
+
+    app.textures.get_mut(&cell).unwrap().get_or_insert_with(|| {
+        let (img, name) = match cell {
+            Cell::Star => (load_image(IMAGES[0]).unwrap(), "star_img"),
+            Cell::Piece(color, piece) => (load_image(IMAGES[img_id_map(color, piece)]).unwrap(), "others"), // TODO fix
+            Cell::Empty | Cell::Obstacle => panic!("invalid Image request"),
+        };
         ui.ctx().load_texture(name, img, TextureOptions::default())
     });
 
-    app.textures[&img_id].as_ref().unwrap()
+    app.textures[&cell].as_ref().unwrap()
+}
+
+// paints whatever is on a board cell. Obstacles are drawn as blocks, everything else uses its texture.
+fn paint_cell(app: &mut MyApp, ui: &mut Ui, cell: Cell, rect: Rect) {
+    match cell {
+        Cell::Empty => (),
+        Cell::Obstacle => {
+            let size = rect.width();
+            ui.painter()
+                .rect(rect.shrink(size / 10.0), size / 10.0, Color32::from_gray(90), Stroke::new(size / 20.0, Color32::from_gray(40)));
+        }
+        Cell::Star | Cell::Piece(..) => {
+            let texture = get_texture(app, ui, cell);
+            // Show the image:
+            egui::Image::new(texture, texture.size_vec2()).paint_at(ui, rect);
+        }
+    }
 }
 
 impl eframe::App for MyApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        if !self.newer_save {
+            eframe::set_value(storage, eframe::APP_KEY, &self.saved_state());
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        self.poll_solve();
+        // rejected move messages fade after a couple of seconds
+        let now = ctx.input().time;
+        if matches!(self.move_error, Some((_, time)) if now - time > MOVE_ERROR_SECS) {
+            self.move_error = None;
+        }
+        // the round's clock starts once its board is on screen
+        self.round_started.get_or_insert(now);
+        if let Some(star) = self.replay.as_mut().and_then(|replay| replay.tick(now)) {
+            play_sound(if star { "capture" } else { "move" });
+        }
+        // Ctrl+Z and Ctrl+Y or Ctrl+Shift+Z step through the moves, unless a text field is taking the keys
+        if !ctx.wants_keyboard_input() {
+            let (undo, redo) = {
+                let mut input = ctx.input_mut();
+                let redo = input.consume_key(Modifiers::COMMAND, Key::Y) || input.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z);
+                (input.consume_key(Modifiers::COMMAND, Key::Z), redo)
+            };
+            if undo {
+                self.undo_move();
+            }
+            if redo {
+                self.redo_move();
+            }
+        }
         // Controls styles
         let mut visuals = if !self.side_panel_dark_mode {
             let mut v = egui::Visuals::light();
@@ -276,16 +742,33 @@ impl eframe::App for MyApp {
 
                     ui.horizontal(|ui| {
                         ui.label(egui::RichText::new(format!("🔥: {}", self.streak)).color(Color32::RED).strong());
+                        if ui.button("Stats").clicked() {
+                            self.show_stats ^= true;
+                        }
+                        ui.menu_button("Reset progress", |ui| {
+                            ui.label("Points, streak, stats and pack progress go back to zero. Settings are kept.");
+                            if ui.button("Reset").clicked() {
+                                self.points = 0;
+                                self.streak = 0;
+                                self.pack_progress.clear();
+                                self.history = RoundHistory::default();
+                                self.session_start = 0;
+                                ui.close_menu();
+                            }
+                        });
                     });
 
                     // show win msgs
                     if !self.in_timed_round && self.board.num_star_cnt == 0 && !self.auto_play && !self.in_game {
                         let mut msg = "You were close!";
                         let msg_color = Color32::RED;
-                        if self.cur_move_cnt == self.optimal_move_cnt {
+                        if Some(self.cur_move_cnt) == self.optimal_move_cnt {
                             msg = "Excellent! 🔥🔥🔥";
                         }
                         ui.label(egui::RichText::new(msg).color(msg_color));
+                        if Some(self.cur_move_cnt) == self.optimal_move_cnt && self.route_cnt > 1 {
+                            ui.label(format!("You found one of {} optimal routes", self.route_cnt));
+                        }
                     }
 
                     match self.last_timed_game {
@@ -297,22 +780,207 @@ impl eframe::App for MyApp {
                     }
 
                     ui.collapsing(" How to play:", |ui| {
-                        ui.label("Try to collect all the stars with as few moves as possible! There's also a timed mode if you are up for the challenge! The timer is set in seconds.");
+                        ui.label("Try to collect all the stars with as few moves as possible! Black pieces guard squares you can't land on, but you can capture them if nothing defends them. There's also a timed mode if you are up for the challenge! The timer is set in seconds.");
                         ui.add_space(2.0);
                     });
 
                     if !self.in_timed_round {
+                        ui.horizontal_wrapped(|ui| {
+                            ui.radio_value(&mut self.choice_piece, Piece::Queen, "Queen");
+                            ui.radio_value(&mut self.choice_piece, Piece::Knight, "Knight");
+                            ui.radio_value(&mut self.choice_piece, Piece::Rook, "Rook");
+                            ui.radio_value(&mut self.choice_piece, Piece::Bishop, "Bishop");
+                            ui.radio_value(&mut self.choice_piece, Piece::King, "King");
+                            ui.radio_value(&mut self.choice_piece, Piece::Pawn, "Pawn");
+                        });
+
+                        ui.horizontal_wrapped(|ui| {
+                            ui.label("Extra pieces: ");
+                            for (piece, name) in [(Piece::Queen, "Queen"), (Piece::Knight, "Knight"), (Piece::Rook, "Rook"), (Piece::Bishop, "Bishop"), (Piece::King, "King")] {
+                                let mut chosen = self.extra_pieces.contains(&piece);
+                                // the player can't have more pieces than the solver tracks
+                                let full = self.extra_pieces.len() + 1 >= chess::MAX_PLAYER_PIECES;
+                                if ui.add_enabled(chosen || !full, egui::Checkbox::new(&mut chosen, name)).changed() {
+                                    if chosen {
+                                        self.extra_pieces.push(piece);
+                                    } else {
+                                        self.extra_pieces.retain(|&p| p != piece);
+                                    }
+                                }
+                            }
+                        });
+
+                        if self.choice_piece == Piece::Pawn {
+                            ui.horizontal(|ui| {
+                                ui.label("Promote to: ");
+                                ui.radio_value(&mut self.promotion_piece, Piece::Queen, "Queen");
+                                ui.radio_value(&mut self.promotion_piece, Piece::Knight, "Knight");
+                                ui.radio_value(&mut self.promotion_piece, Piece::Rook, "Rook");
+                                ui.radio_value(&mut self.promotion_piece, Piece::Bishop, "Bishop");
+                            });
+                        }
+
+                        ui.horizontal_wrapped(|ui| {
+                            ui.label("Board size: ");
+                            for size in chess::BOARD_SIZES {
+                                ui.radio_value(&mut self.board_size, size, format!("{}x{}", size, size));
+                            }
+                        });
+                        // smaller boards fit fewer things
+                        let limits = CountLimits::for_size(self.board_size);
+                        self.enemy_cnt = self.enemy_cnt.min(limits.enemies);
+                        self.obstacle_cnt = self.obstacle_cnt.min(limits.obstacles);
+
+                        ui.horizontal(|ui| {
+                            ui.label("Difficulty: ");
+                            ui.radio_value(&mut self.difficulty, Difficulty::Easy, "Easy").on_hover_text("3 to 5 moves");
+                            ui.radio_value(&mut self.difficulty, Difficulty::Medium, "Medium").on_hover_text("7 to 10 moves");
+                            ui.radio_value(&mut self.difficulty, Difficulty::Hard, "Hard").on_hover_text("12 to 16 moves with only a few ways to do it");
+                        });
+
                         ui.horizontal(|ui| {
-                            ui.radio_value(&mut self.choice_piece, chess::QUEEN_WHITE, "Queen");
-                            ui.radio_value(&mut self.choice_piece, chess::KNIGHT_WHITE, "Knight");
-                            ui.radio_value(&mut self.choice_piece, chess::ROOK_WHITE, "Rook");
+                            ui.label("Number of enemies: ");
+                            ui.add(egui::Slider::new(&mut self.enemy_cnt, 0..=limits.enemies));
                         });
 
                         ui.horizontal(|ui| {
-                            ui.label("Number of stars: ");
-                            ui.add(egui::Slider::new(&mut self.star_cnt, 1..=18));
+                            ui.label("Number of obstacles: ");
+                            ui.add(egui::Slider::new(&mut self.obstacle_cnt, 0..=limits.obstacles));
+                        });
+
+                        match self.seed {
+                            Some(seed) => ui.label(format!("Board seed: {}", seed)),
+                            None => ui.label("Board seed: none, the puzzle was pasted or is from a pack"),
+                        };
+                        ui.horizontal(|ui| {
+                            ui.label("Seed: ");
+                            ui.add(egui::TextEdit::singleline(&mut self.seed_text).desired_width(160.0));
+                            let seed = self.seed_text.trim().parse::<u64>().ok();
+                            // the same seed and settings always give the same board
+                            if ui.add_enabled(seed.is_some(), Button::new("Load")).on_hover_text("Set up the board for this seed with the settings above").clicked() {
+                                self.seed = seed;
+                                self.active_pack = None;
+                                self.start_round(self.seeded_board(seed.unwrap()));
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Puzzle: ");
+                            ui.add(egui::TextEdit::singleline(&mut self.puzzle_text).hint_text("paste a puzzle here").desired_width(220.0));
+                        });
+                        ui.horizontal(|ui| {
+                            // the board as it is now, so copy before moving to share the whole puzzle
+                            if ui.button("Copy puzzle").clicked() {
+                                self.puzzle_text = self.board.to_notation();
+                                ui.output().copied_text = self.puzzle_text.clone();
+                                self.puzzle_error = None;
+                            }
+                            if ui.button("Paste puzzle").clicked() {
+                                match LiBoard::from_notation(&self.puzzle_text) {
+                                    Ok(board) => {
+                                        self.puzzle_error = None;
+                                        self.seed = None;
+                                        self.active_pack = None;
+                                        self.start_round(board);
+                                    }
+                                    Err(err) => self.puzzle_error = Some(err),
+                                }
+                            }
+                        });
+                        if let Some(err) = &self.puzzle_error {
+                            ui.colored_label(Color32::RED, err.to_string());
+                        }
+
+                        ui.collapsing(" Puzzle packs:", |ui| {
+                            let mut chosen = None;
+                            for (pack_idx, pack) in self.packs.iter().enumerate() {
+                                let progress = self.pack_progress.get(&pack.title);
+                                let solved = progress.map_or(0, PackProgress::solved_cnt);
+                                if ui.button(format!("{} ({}/{})", pack.title, solved, pack.puzzles.len())).clicked() {
+                                    // carry on from the first unsolved puzzle
+                                    let last = pack.puzzles.len() - 1;
+                                    chosen = Some((pack_idx, progress.map_or(0, |progress| progress.next_puzzle(last, pack.puzzles.len()))));
+                                }
+                            }
+                            if let Some((pack_idx, puzzle_idx)) = chosen {
+                                self.start_pack_puzzle(pack_idx, puzzle_idx);
+                            }
+                            #[cfg(not(target_arch = "wasm32"))]
+                            ui.horizontal(|ui| {
+                                ui.add(egui::TextEdit::singleline(&mut self.pack_path).hint_text("path to a pack file").desired_width(160.0));
+                                if ui.button("Load pack").clicked() {
+                                    match PuzzlePack::load(std::path::Path::new(self.pack_path.trim())) {
+                                        Ok(pack) => {
+                                            self.pack_error = None;
+                                            // loading a pack again picks up changes to it
+                                            let pack_idx = match self.packs.iter().position(|p| p.title == pack.title) {
+                                                Some(pack_idx) => {
+                                                    self.packs[pack_idx] = pack;
+                                                    pack_idx
+                                                }
+                                                None => {
+                                                    self.packs.push(pack);
+                                                    self.packs.len() - 1
+                                                }
+                                            };
+                                            self.start_pack_puzzle(pack_idx, 0);
+                                        }
+                                        Err(err) => self.pack_error = Some(err.to_string()),
+                                    }
+                                }
+                            });
+                            if let Some(err) = &self.pack_error {
+                                ui.colored_label(Color32::RED, err);
+                            }
                         });
+
+                        if let Some((pack_idx, puzzle_idx)) = self.active_pack {
+                            let pack = &self.packs[pack_idx];
+                            let puzzle = &pack.puzzles[puzzle_idx];
+                            ui.label(RichText::new(format!("{}: puzzle {} of {}", pack.title, puzzle_idx + 1, pack.puzzles.len())).strong());
+                            if let Some(target) = puzzle.target_moves {
+                                ui.label(format!("Target: {} moves", target));
+                            }
+                            if let Some(best) = self.pack_progress.get(&pack.title).and_then(|progress| progress.best.get(puzzle_idx).copied().flatten()) {
+                                ui.label(format!("Your best: {} moves", best));
+                            }
+                            if let Some(hint) = &puzzle.hint {
+                                ui.collapsing("Hint", |ui| ui.label(hint));
+                            }
+                            let puzzle_cnt = pack.puzzles.len();
+                            ui.horizontal(|ui| {
+                                if ui.button("Previous").clicked() {
+                                    self.start_pack_puzzle(pack_idx, (puzzle_idx + puzzle_cnt - 1) % puzzle_cnt);
+                                }
+                                if ui.button("Next puzzle").clicked() {
+                                    self.start_pack_puzzle(pack_idx, (puzzle_idx + 1) % puzzle_cnt);
+                                }
+                                if ui.button("Leave pack").clicked() {
+                                    self.active_pack = None;
+                                    let board = self.new_board();
+                                    self.start_round(board);
+                                }
+                            });
+                        }
                     }
+
+                    ui.collapsing(" Solver:", |ui| {
+                        ui.horizontal_wrapped(|ui| {
+                            ui.radio_value(&mut self.solver_strategy, SolverStrategy::AStar, SolverStrategy::AStar.name());
+                            // the web build solves a bit per frame, which only A* can do
+                            ui.add_enabled_ui(!cfg!(target_arch = "wasm32"), |ui| {
+                                ui.radio_value(&mut self.solver_strategy, SolverStrategy::BoundedBfs, SolverStrategy::BoundedBfs.name());
+                                ui.radio_value(&mut self.solver_strategy, SolverStrategy::Bidirectional, SolverStrategy::Bidirectional.name());
+                                ui.radio_value(&mut self.solver_strategy, SolverStrategy::Bfs, SolverStrategy::Bfs.name());
+                            })
+                            .response
+                            .on_disabled_hover_text("Only A* can run in the browser without stalling the page");
+                        });
+                        // some strategies hand boards they can't search to another one
+                        ui.label(format!("Searched with: {}", self.solver_stats.strategy.name()));
+                        ui.label(format!("Expanded nodes: {}", self.solver_stats.expanded));
+                        ui.label(format!("Generated nodes: {}", self.solver_stats.generated));
+                    });
                     egui::Grid::new("my_grid").num_columns(2).spacing([23.0, 4.0]).show(ui, |ui| {
                         // pick board colors
 
@@ -379,14 +1047,24 @@ impl eframe::App for MyApp {
                                 self.in_timed_round = false;
                             }
 
-                            self.in_game = true;
-                            self.board = LiBoard::new(self.star_cnt as i8, self.choice_piece);
-                            self.cur_move_cnt = 0;
-                            (self.optimal_move_cnt, self.solution_path) = self.board.num_optimal_moves_to_star();
-                            self.arrows_to_draw.clear();
+                            let board = self.new_board();
+                            self.start_round(board);
+                        }
+                        ui.add_space(3.0);
+                        if ui.button("Retry").on_hover_text("Start this board again. Later attempts score less.").clicked() {
+                            self.retry_round();
                         }
                         ui.add_space(3.0);
 
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(self.in_game && !self.undo_stack.is_empty(), Button::new("Undo move")).on_hover_text("Ctrl+Z").clicked() {
+                                self.undo_move();
+                            }
+                            if ui.add_enabled(self.in_game && !self.redo_stack.is_empty(), Button::new("Redo move")).on_hover_text("Ctrl+Y").clicked() {
+                                self.redo_move();
+                            }
+                        });
+                        ui.add_space(3.0);
                         if ui.button("Undo Drawing").clicked() {
                             self.arrows_to_draw.pop();
                         }
@@ -397,24 +1075,37 @@ impl eframe::App for MyApp {
                         ui.add_space(3.0);
                         if ui
                             .add(
-                                Button::new(if self.show_solution {
-                                    RichText::new("Hide Solution Path").color(Color32::WHITE).strong()
-                                } else {
+                                Button::new(if !self.show_solution {
                                     RichText::new("Show Solution Path").color(Color32::WHITE)
+                                } else if self.solution_paths.len() > 1 {
+                                    RichText::new(format!("Next Solution Path ({}/{})", self.shown_route + 1, self.solution_paths.len())).color(Color32::WHITE).strong()
+                                } else {
+                                    RichText::new("Hide Solution Path").color(Color32::WHITE).strong()
                                 })
                                 .fill(if self.side_panel_dark_mode { Color32::DARK_RED } else { Color32::RED }),
                             )
                             .clicked()
                         {
-                            self.show_solution ^= true;
+                            // cycle through the optimal routes, going back round to the first
+                            if !self.show_solution {
+                                self.show_solution = true;
+                                self.shown_route = 0;
+                            } else if self.solution_paths.len() > 1 {
+                                self.shown_route = (self.shown_route + 1) % self.solution_paths.len();
+                            } else {
+                                self.show_solution = false;
+                            }
+                        }
+                        if self.show_solution && self.solution_paths.len() > 1 && ui.button("Hide Solution Path").clicked() {
+                            self.show_solution = false;
                         }
+                        ui.add_space(3.0);
+                        self.replay_controls(ui, now);
 
-                        if self.auto_play && self.board.num_star_cnt == 0 {
-                            self.in_game = true;
-                            self.board = LiBoard::new(self.star_cnt as i8, self.choice_piece);
-                            self.cur_move_cnt = 0;
-                            (self.optimal_move_cnt, self.solution_path) = self.board.num_optimal_moves_to_star();
-                            self.arrows_to_draw.clear();
+                        // wait for the finished round to be scored
+                        if self.auto_play && self.board.num_star_cnt == 0 && !self.in_game {
+                            let board = self.new_board();
+                            self.start_round(board);
                         }
                     });
                 });
@@ -467,13 +1158,10 @@ impl eframe::App for MyApp {
                         self.in_timed_round = false;
                         self.last_timed_game = Some(self.cur_timed_num_wins);
                         self.cur_timed_num_wins = 0;
-                        self.cur_move_cnt = 0;
                         self.timer = cur_time;
                         // restart and create a new game
-                        self.in_game = true;
-                        self.board = LiBoard::new(self.star_cnt as i8, self.choice_piece);
-                        (self.optimal_move_cnt, self.solution_path) = self.board.num_optimal_moves_to_star();
-                        self.arrows_to_draw.clear();
+                        let board = self.new_board();
+                        self.start_round(board);
                     } else {
                         show_progress_bar = true;
                     }
@@ -491,8 +1179,21 @@ impl eframe::App for MyApp {
 
                 ui.add_space(3.0);
                 ui.label("Number of current moves: ".to_owned() + &self.cur_move_cnt.to_string());
+                if self.attempt > 1 {
+                    ui.label(format!("Attempt {}", self.attempt));
+                }
                 ui.add_space(3.0);
-                ui.label("Optimal: ".to_owned() + &self.optimal_move_cnt.to_string());
+                match self.optimal_move_cnt {
+                    _ if self.solve_task.is_some() => ui.label("Optimal: computing…"),
+                    Some(v) => ui.label("Optimal: ".to_owned() + &v.to_string()),
+                    None => ui.label("Optimal: unsolvable"),
+                };
+                // the generator settles for the closest board it found when the settings can't reach the difficulty
+                if let (Some(difficulty), Some(move_cnt), None) = (self.round_difficulty, self.optimal_move_cnt, &self.solve_task) {
+                    if !difficulty.target().is_met(move_cnt, self.route_cnt) {
+                        ui.label(format!("No {:?} board found with these settings, this is the closest", difficulty));
+                    }
+                }
                 ui.add_space(3.0);
                 if show_progress_bar {
                     ui.label(format!("Time left: {}", self.starting_timer - (cur_time - self.timer)));
@@ -508,12 +1209,13 @@ impl eframe::App for MyApp {
                 // leave some space for controls on the bottom
                 let Vec2 { x, y } = ui.available_size();
                 let (board_rect, _) = ui.allocate_at_least(Vec2::new(x, y - 50.0), Sense::click());
-                let size = ((board_rect.max.x - board_rect.min.x) / 8.0).min((board_rect.max.y - board_rect.min.y) / 8.0); // width of square
-                self.board_width = Some(size * 8.0);
+                let squares = self.board.size() as f32; // squares along each side
+                let size = ((board_rect.max.x - board_rect.min.x) / squares).min((board_rect.max.y - board_rect.min.y) / squares); // width of square
+                self.board_width = Some(size * squares);
                 let mut piece_state = PieceStates::NoDrag;
                 ui.add_space(5.0);
-                for i in 0..8 {
-                    for j in 0..8 {
+                for i in 0..self.board.size() {
+                    for j in 0..self.board.size() {
                         let sq = Rect {
                             min: Pos2 {
                                 x: j as f32 * size + board_rect.min.x,
@@ -535,9 +1237,21 @@ impl eframe::App for MyApp {
                         let piece_resp = ui.allocate_rect(sq, Sense::drag());
 
                         let cur_input_pos = ctx.input().pointer.interact_pos();
-                        let piece_being_moved = self.board.board[i as usize][j as usize];
+                        // a replay shows its own board, which can't be played on
+                        let piece_being_moved = match &self.replay {
+                            Some(replay) => replay.cell(Square::new(i, j), now),
+                            None => self.board.board[i][j],
+                        };
                         // paint squares
                         ui.painter().rect_filled(sq, 0.0, temp_color);
+                        if self.replay.is_some() {
+                            paint_cell(self, ui, piece_being_moved, sq);
+                            continue;
+                        }
+                        // point at whatever stopped the last move
+                        if self.move_error.and_then(|(err, _)| err.square()) == Some(Square::new(i, j)) {
+                            ui.painter().rect_filled(sq, 0.0, Color32::from_rgba_unmultiplied(200, 0, 0, 110));
+                        }
 
                         // Handle arrow drags
                         if piece_resp.dragged_by(PointerButton::Secondary) {
@@ -547,21 +1261,15 @@ impl eframe::App for MyApp {
                                 let goal_j = (a.x - board_rect.min.x) / size;
                                 let goal_i = (a.y - board_rect.min.y) / size;
                                 piece_state = PieceStates::ArrowDragged(ArrowMove {
-                                    start_i: i,
-                                    start_j: j,
-                                    end_i: goal_i as usize,
-                                    end_j: goal_j as usize,
+                                    start: Square::new(i, j),
+                                    end: Square::new(goal_i as usize, goal_j as usize),
                                 });
                             }
-                            if piece_being_moved != 0 {
-                                let texture = get_texture(self, ui, piece_being_moved);
-                                // Show the image:
-                                egui::Image::new(texture, texture.size_vec2()).paint_at(ui, sq);
-                            }
-                        } else if piece_resp.dragged_by(PointerButton::Primary) && piece_being_moved != 0 {
+                            paint_cell(self, ui, piece_being_moved, sq);
+                        } else if piece_resp.dragged_by(PointerButton::Primary) && matches!(piece_being_moved, Cell::Piece(Color::White, _)) {
                             self.primary_clicked = true;
                             // currently dragging.. draw the texture at current mouse pos
-                            if cur_input_pos.is_some() && piece_being_moved != 0 {
+                            if cur_input_pos.is_some() {
                                 let cur_input_pos = cur_input_pos.unwrap();
                                 // draw at the center of mouse when grabbed
                                 let start_of_rec = Pos2 {
@@ -574,7 +1282,7 @@ impl eframe::App for MyApp {
                                 };
                                 let image_rect = Rect { min: start_of_rec, max: end_of_rec };
 
-                                piece_state = PieceStates::Dragged(image_rect, piece_being_moved);
+                                piece_state = PieceStates::Dragged(image_rect, piece_being_moved, Square::new(i, j));
                             }
                         }
                         // arrow drag released
@@ -586,20 +1294,14 @@ impl eframe::App for MyApp {
                                 let goal_j = (a.x - board_rect.min.x) / size;
                                 let goal_i = (a.y - board_rect.min.y) / size;
                                 piece_state = PieceStates::ArrowDragReleased(ArrowMove {
-                                    start_i: i,
-                                    start_j: j,
-                                    end_i: goal_i as usize,
-                                    end_j: goal_j as usize,
+                                    start: Square::new(i, j),
+                                    end: Square::new(goal_i as usize, goal_j as usize),
                                 });
                             }
-                            if piece_being_moved != 0 {
-                                let texture = get_texture(self, ui, piece_being_moved);
-                                // Show the image:
-                                egui::Image::new(texture, texture.size_vec2()).paint_at(ui, sq);
-                            }
+                            paint_cell(self, ui, piece_being_moved, sq);
                         }
                         // Handle primary button drags
-                        else if self.primary_clicked && piece_resp.drag_released() && piece_being_moved != STAR_VALUE {
+                        else if self.primary_clicked && piece_resp.drag_released() && piece_being_moved != Cell::Star {
                             self.primary_clicked = false;
                             // done dragging here.. potentially update board state for next frame
                             let a = ctx.input().pointer.interact_pos();
@@ -620,55 +1322,46 @@ impl eframe::App for MyApp {
                                 piece_state = PieceStates::DragReleased(
                                     image_rect,
                                     MovePiece {
-                                        i: i as usize,
-                                        j: j as usize,
-                                        goal_i: goal_i as usize,
-                                        goal_j: goal_j as usize,
+                                        from: Square::new(i, j),
+                                        to: Square::new(goal_i as usize, goal_j as usize),
                                     },
                                 );
                             }
                         } else {
                             // paint image
-                            if piece_being_moved != 0 {
-                                let texture = get_texture(self, ui, piece_being_moved);
-                                // Show the image:
-                                egui::Image::new(texture, texture.size_vec2()).paint_at(ui, sq);
-                            }
+                            paint_cell(self, ui, piece_being_moved, sq);
                         }
                     }
                 }
 
                 // Draw the "dragged piece"
                 match piece_state {
-                    PieceStates::Dragged(piece_rect, img_id) => {
+                    PieceStates::Dragged(piece_rect, img_id, from) => {
+                        // mark where the piece can go
+                        for m in self.board.legal_moves(from) {
+                            let center = Pos2::new((m.to.j as f32 + 0.5) * size + board_rect.min.x, (m.to.i as f32 + 0.5) * size + board_rect.min.y);
+                            ui.painter().circle_filled(center, size / 8.0, self.arrow_color);
+                        }
                         let texture = get_texture(self, ui, img_id);
 
                         // Show the image:
                         egui::Image::new(texture, texture.size_vec2()).paint_at(ui, piece_rect);
                     }
                     PieceStates::DragReleased(piece_rect, move_piece) => {
-                        if self.board.validate_move(&move_piece).is_valid() {
-                            if self.board.board[move_piece.goal_i][move_piece.goal_j] == chess::STAR_VALUE {
-                                play_sound("capture");
-                                self.board.num_star_cnt -= 1;
-                            } else {
-                                play_sound("move");
-                            }
-                            self.board.update_board(&move_piece);
-                            if self.in_game {
-                                self.cur_move_cnt += 1;
-                            }
-                            if self.board.num_star_cnt == 0 && self.in_game {
-                                play_sound("win");
-                            }
+                        let result = self.board.validate_move(&move_piece);
+                        if result.is_ok() {
+                            self.move_error = None;
+                            self.play_move(&move_piece);
+                        }
+                        match result {
+                            // putting the piece back down where it was isn't a mistake
+                            Ok(()) | Err(MoveError::SameSquare) => (),
+                            Err(err) => self.move_error = Some((err, now)),
                         }
                         // validate goali and j so they are within bounds
-                        if !(move_piece.goal_i >= 8 || move_piece.goal_j >= 8) {
-                            let img_id = self.board.board[move_piece.goal_i][move_piece.goal_j];
-                            if img_id != 0 {
-                                let texture = get_texture(self, ui, img_id);
-                                egui::Image::new(texture, texture.size_vec2()).paint_at(ui, piece_rect);
-                            }
+                        if move_piece.to.in_bounds(self.board.size()) {
+                            let cell = self.board[move_piece.to];
+                            paint_cell(self, ui, cell, piece_rect);
                         }
                     }
                     PieceStates::ArrowDragReleased(arrow_move) => {
@@ -685,30 +1378,60 @@ impl eframe::App for MyApp {
                     self.draw_arrow(arrow_move.clone(), ui.painter(), size, board_rect);
                 }
 
-                if self.show_solution {
-                    for move_piece in &self.solution_path {
+                if let Some(replay) = &self.replay {
+                    for (idx, move_piece) in replay.played().enumerate() {
+                        self.draw_numbered_arrow(move_piece.clone().into(), idx + 1, ui.painter(), size, board_rect);
+                    }
+                    // slide the piece of the last move over the arrows
+                    if let Some((cell, from, to, done)) = replay.sliding_cell(now) {
+                        let square_pos = |sq: Square| Vec2::new(sq.j as f32, sq.i as f32) * size;
+                        let min = board_rect.min + square_pos(from) + (square_pos(to) - square_pos(from)) * done;
+                        paint_cell(self, ui, cell, Rect::from_min_size(min, Vec2::splat(size)));
+                    }
+                } else if let (true, Some(path)) = (self.show_solution, self.solution_paths.get(self.shown_route)) {
+                    for move_piece in path {
                         self.draw_arrow(move_piece.clone().into(), ui.painter(), size, board_rect);
                     }
                 }
 
-                // Update game stats when all the stars are collected
-                if self.board.num_star_cnt == 0 && self.in_game {
+                // Update game stats when all the stars are collected, once the optimal count is known
+                if self.board.num_star_cnt == 0 && self.in_game && self.solve_task.is_none() {
                     // clear arrow drawings
                     self.arrows_to_draw.clear();
                     self.in_game = false;
-                    match (self.cur_move_cnt - self.optimal_move_cnt).abs() {
-                        // handle point system 100 : perfect , 10, off by 1
-                        0 => {
-                            self.cur_timed_num_wins += 1;
-                            self.points += 100;
-                            self.streak += 1;
-                        }
-                        1 => {
-                            self.points += 10;
-                            self.streak = 0;
-                        }
-                        _ => self.streak = 0,
+                    let piece = match self.start_board[self.start_board.pieces[0]] {
+                        Cell::Piece(_, piece) => piece,
+                        cell => unreachable!("the first piece's square has {:?} on it", cell),
+                    };
+                    let round = RoundRecord {
+                        piece,
+                        star_cnt: self.start_board.num_star_cnt,
+                        optimal_moves: self.optimal_move_cnt,
+                        moves: self.cur_move_cnt,
+                        secs: self.round_started.map_or(0.0, |started| (now - started) as f32),
+                        timed: self.in_timed_round,
+                        undone: self.undone,
+                        attempt: self.attempt,
+                    };
+                    if round.is_perfect() {
+                        self.cur_timed_num_wins += 1;
                     }
+                    let (points, streak) = round.score(self.streak);
+                    self.points += points;
+                    self.streak = streak;
+                    self.history.record(round, self.streak);
+                    if let Some((pack_idx, puzzle_idx)) = self.active_pack {
+                        let pack = &self.packs[pack_idx];
+                        // puzzles without a target have to be cleared in the optimal count
+                        let target = pack.puzzles[puzzle_idx].target_moves.or(self.optimal_move_cnt);
+                        self.pack_progress
+                            .entry(pack.title.clone())
+                            .or_insert_with(|| PackProgress::new(pack.puzzles.len()))
+                            .record(puzzle_idx, self.cur_move_cnt, target);
+                    }
+                }
+                if let Some((err, _)) = self.move_error {
+                    ui.label(RichText::new(err.message(self.board.size())).color(Color32::RED));
                 }
                 ui.add_space(10.0);
                 // slow mode for debugging
@@ -716,10 +1439,31 @@ impl eframe::App for MyApp {
                 // while i > 0  { i -= 20;}
             });
 
+        if self.show_stats {
+            egui::Window::new("Stats").open(&mut self.show_stats).resizable(false).show(ctx, |ui| {
+                stats_window(ui, &self.history, self.session_start);
+            });
+        }
+
+        // clear the move message on time
+        if self.move_error.is_some() {
+            ctx.request_repaint_after(Duration::from_secs_f64(MOVE_ERROR_SECS));
+        }
         // If a timed round is happening, repaint every second.
         if self.in_timed_round {
             ctx.request_repaint_after(Duration::from_secs(1));
         }
+        // keep the replay moving
+        if self.replay.as_ref().map_or(false, |replay| replay.playing || replay.sliding_cell(now).is_some()) {
+            ctx.request_repaint_after(Duration::from_millis(16));
+        }
+        // keep checking on the solver. The web build solves a bit each poll, so it needs them as often as it can get.
+        if self.solve_task.is_some() {
+            #[cfg(not(target_arch = "wasm32"))]
+            ctx.request_repaint_after(Duration::from_millis(50));
+            #[cfg(target_arch = "wasm32")]
+            ctx.request_repaint();
+        }
     }
 
     fn clear_color(&self, _visuals: &egui::Visuals) -> egui::Rgba {
@@ -728,6 +1472,79 @@ impl eframe::App for MyApp {
     }
 }
 
+// how many of the latest rounds the stats chart shows
+const CHART_ROUNDS: usize = 20;
+
+fn stats_window(ui: &mut egui::Ui, history: &RoundHistory, session_start: usize) {
+    use egui::plot::{Bar, BarChart, Plot};
+    let percent = |summary: &Summary| summary.accuracy().map_or("-".to_owned(), |accuracy| format!("{:.0}%", accuracy * 100.0));
+    let average = |value: Option<f32>| value.map_or("-".to_owned(), |value| format!("{:.1}", value));
+    let session = Summary::new(&history.rounds[session_start..]);
+    let lifetime = Summary::new(&history.rounds);
+    egui::Grid::new("stats_summary").striped(true).show(ui, |ui| {
+        ui.label("");
+        ui.label(RichText::new("This session").strong());
+        ui.label(RichText::new("All time").strong());
+        ui.end_row();
+        let row = |ui: &mut egui::Ui, name: &str, value: &dyn Fn(&Summary) -> String| {
+            ui.label(name);
+            ui.label(value(&session));
+            ui.label(value(&lifetime));
+            ui.end_row();
+        };
+        row(ui, "Rounds", &|summary| summary.round_cnt.to_string());
+        row(ui, "Timed rounds", &|summary| summary.timed_cnt.to_string());
+        row(ui, "Optimal", &percent);
+        row(ui, "Extra moves on average", &|summary| average(summary.average_excess));
+        row(ui, "Seconds a round", &|summary| average(summary.average_secs));
+    });
+    ui.label(format!("Best streak: {}", history.best_streak));
+
+    ui.add_space(6.0);
+    egui::Grid::new("stats_by_piece").striped(true).show(ui, |ui| {
+        ui.label(RichText::new("Piece").strong());
+        ui.label(RichText::new("Rounds").strong());
+        ui.label(RichText::new("Optimal").strong());
+        ui.label(RichText::new("Extra moves").strong());
+        ui.end_row();
+        for (piece, summary) in stats::by_piece(&history.rounds) {
+            ui.label(piece.to_string());
+            ui.label(summary.round_cnt.to_string());
+            ui.label(percent(&summary));
+            ui.label(average(summary.average_excess));
+            ui.end_row();
+        }
+    });
+
+    // extra moves in the latest rounds, green when it was optimal
+    ui.add_space(6.0);
+    ui.label("Extra moves in recent rounds:");
+    let recent = &history.rounds[history.rounds.len().saturating_sub(CHART_ROUNDS)..];
+    let bars = recent
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, round)| {
+            let excess = round.excess_moves()?;
+            let color = if excess == 0 { Color32::DARK_GREEN } else { Color32::RED };
+            // perfect rounds still get a sliver of a bar so they show up
+            Some(
+                Bar::new(idx as f64, f64::from(excess).max(0.1))
+                    .name(format!("{} with {} stars", round.piece, round.star_cnt))
+                    .fill(color),
+            )
+        })
+        .collect();
+    Plot::new("stats_chart")
+        .height(120.0)
+        .width(260.0)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .include_y(0.0)
+        .show_x(false)
+        .show(ui, |plot_ui| plot_ui.bar_chart(BarChart::new(bars).width(0.7)));
+}
+
 pub fn arrow(painter: &Painter, origin: Pos2, vec: Vec2, stroke: Stroke) {
     use egui::emath::*;
     let rot = Rot2::from_angle(std::f32::consts::TAU / 6.0);
@@ -739,3 +1556,136 @@ pub fn arrow(painter: &Painter, origin: Pos2, vec: Vec2, stroke: Stroke) {
     painter.line_segment([tip, tip - tip_length * (rot * dir)], stroke);
     painter.line_segment([tip, tip - tip_length * (rot.inverse() * dir)], stroke);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saved_state_round_trip() {
+        let mut progress = PackProgress::new(3);
+        progress.record(1, 4, Some(4));
+        let saved = SavedState {
+            points: 250,
+            streak: 3,
+            pack_progress: HashMap::from([("Openers".to_owned(), progress)]),
+            choice_piece: Piece::Pawn,
+            extra_pieces: vec![Piece::Knight, Piece::King],
+            promotion_piece: Piece::Bishop,
+            board_size: 10,
+            difficulty: Difficulty::Hard,
+            enemy_cnt: 3,
+            obstacle_cnt: 5,
+            side_panel_dark_mode: true,
+            starting_timer: 90,
+            ..SavedState::default()
+        };
+        let back: SavedState = serde_json::from_str(&serde_json::to_string(&saved).unwrap()).unwrap();
+        assert_eq!(saved, back);
+        // settings the side panel could pick come back as they were
+        assert_eq!(saved, back.validated());
+    }
+
+    #[test]
+    fn test_first_round() {
+        let app = MyApp::default();
+        // the round starts on the board the player sees
+        assert_eq!(app.start_board.to_notation(), app.board.to_notation());
+        assert_eq!((1, true), (app.attempt, app.in_game));
+    }
+
+    #[test]
+    fn test_undo_redo_retry() {
+        let mut app = MyApp::default();
+        let board = LiBoard::from_notation("8 Ra1 a8,h8 - -").unwrap();
+        app.start_round(board.clone());
+        let m = |from: (usize, usize), to: (usize, usize)| MovePiece {
+            from: Square::new(from.0, from.1),
+            to: Square::new(to.0, to.1),
+        };
+        app.play_move(&m((7, 0), (0, 0)));
+        app.play_move(&m((0, 0), (0, 3)));
+        assert_eq!((2, 1), (app.cur_move_cnt, app.board.num_star_cnt));
+        // taking a move back marks the round, putting it back doesn't count it twice
+        app.undo_move();
+        app.undo_move();
+        assert_eq!((0, 2, true), (app.cur_move_cnt, app.board.num_star_cnt, app.undone));
+        app.redo_move();
+        assert_eq!((1, 1, 1), (app.cur_move_cnt, app.board.num_star_cnt, app.redo_stack.len()));
+        // a new move drops the moves that were taken back
+        app.play_move(&m((0, 0), (0, 7)));
+        assert_eq!((2, 0), (app.cur_move_cnt, app.board.num_star_cnt));
+        assert!(app.redo_stack.is_empty());
+        // a retry starts over on the same board, counting the attempt
+        app.retry_round();
+        assert_eq!(board.to_notation(), app.board.to_notation());
+        assert_eq!((0, 2, false), (app.cur_move_cnt, app.attempt, app.undone));
+        assert!(app.undo_stack.is_empty() && app.in_game);
+        // getting the same board again doesn't start the count over
+        app.start_round(board.clone());
+        assert_eq!(3, app.attempt);
+        // a different board does
+        app.start_round(LiBoard::from_notation("8 Ra1 a8 - -").unwrap());
+        assert_eq!(1, app.attempt);
+    }
+
+    // keeps values in memory in place of eframe's file
+    #[derive(Default)]
+    struct MemoryStorage(HashMap<String, String>);
+
+    impl eframe::Storage for MemoryStorage {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(key.to_owned(), value);
+        }
+
+        fn flush(&mut self) {}
+    }
+
+    #[test]
+    fn test_save_versions() {
+        use eframe::App;
+        let mut storage = MemoryStorage::default();
+        let saved = SavedState { points: 40, ..SavedState::default() };
+        eframe::set_value(&mut storage, eframe::APP_KEY, &saved);
+        let mut app = MyApp::load(Some(&storage));
+        assert_eq!(40, app.points);
+        app.points = 60;
+        app.save(&mut storage);
+        assert_eq!(Some(60), eframe::get_value::<SavedState>(&storage, eframe::APP_KEY).map(|saved| saved.points));
+        // a newer save isn't read or written over
+        let newer = SavedState {
+            version: SAVE_VERSION + 1,
+            points: 500,
+            ..SavedState::default()
+        };
+        eframe::set_value(&mut storage, eframe::APP_KEY, &newer);
+        let mut app = MyApp::load(Some(&storage));
+        assert_eq!(0, app.points);
+        app.save(&mut storage);
+        assert_eq!(Some(newer), eframe::get_value(&storage, eframe::APP_KEY));
+        // there's no version before the first
+        let older = SavedState {
+            version: 0,
+            points: 9,
+            ..SavedState::default()
+        };
+        assert_eq!(SavedState::default(), older.migrated());
+    }
+
+    #[test]
+    fn test_corrupt_save() {
+        let json = r#"{"board_size": 7, "promotion_piece": "Pawn", "extra_pieces": ["Pawn", "Queen", "Queen", "Rook", "King"], "enemy_cnt": 100, "obstacle_cnt": -3, "starting_timer": 0}"#;
+        let saved = serde_json::from_str::<SavedState>(json).unwrap().validated();
+        let default = SavedState::default();
+        assert_eq!((default.board_size, default.promotion_piece), (saved.board_size, saved.promotion_piece));
+        assert_eq!(vec![Piece::Queen, Piece::Rook], saved.extra_pieces);
+        assert_eq!((CountLimits::for_size(default.board_size).enemies, 0), (saved.enemy_cnt, saved.obstacle_cnt));
+        assert_eq!(default.starting_timer, saved.starting_timer);
+        // a missing field is the default rather than a failed load
+        assert_eq!(default, serde_json::from_str::<SavedState>("{}").unwrap());
+    }
+}
@@ -0,0 +1,384 @@
+use crate::chess::*;
+use crate::movegen::{offset, DIRECTIONS, KNIGHT_OFFSETS};
+use std::fmt;
+use std::sync::Mutex;
+
+// One bit per square, enough for boards up to MAX_BOARD_SIZE
+pub type Mask = u128;
+
+// Move tables for one board size. Squares are bit i * size + j.
+pub struct Tables {
+    pub size: usize,
+    // every square on the board
+    pub all: Mask,
+    pub knight: Vec<Mask>,
+    pub king: Vec<Mask>,
+    // diagonal captures by colour. White pawns move up the board, black pawns move down.
+    pub pawn: [Vec<Mask>; 2],
+    // every square from sq to the edge of the board in each direction, sq itself not included
+    rays: [Vec<Mask>; 8],
+}
+
+// the tables are the same for every board of a size, so only the size is worth printing or comparing
+impl fmt::Debug for Tables {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Tables({}x{})", self.size, self.size)
+    }
+}
+
+impl PartialEq for Tables {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size
+    }
+}
+
+impl Eq for Tables {}
+
+// the tables for a board size, built the first time the size is used
+pub fn tables(size: usize) -> &'static Tables {
+    assert!(size <= MAX_BOARD_SIZE, "{}x{} boards don't fit in a bitboard", size, size);
+    static TABLES: Mutex<Vec<&'static Tables>> = Mutex::new(Vec::new());
+    let mut built = TABLES.lock().unwrap();
+    if let Some(tables) = built.iter().find(|tables| tables.size == size) {
+        return tables;
+    }
+    let tables = Box::leak(Box::new(Tables::new(size)));
+    built.push(tables);
+    tables
+}
+
+impl Tables {
+    fn new(size: usize) -> Tables {
+        let bit = |sq: Square| 1 << (sq.i * size + sq.j);
+        let offset_table = |offsets: &[(i8, i8)]| -> Vec<Mask> {
+            (0..size * size)
+                .map(|idx| {
+                    let from = Square::new(idx / size, idx % size);
+                    offsets.iter().filter_map(|&d| offset(size, from, d)).fold(0, |mask, to| mask | bit(to))
+                })
+                .collect()
+        };
+        let rays = DIRECTIONS.map(|dir| {
+            (0..size * size)
+                .map(|idx| {
+                    let mut ray = 0;
+                    let mut cur = Square::new(idx / size, idx % size);
+                    while let Some(to) = offset(size, cur, dir) {
+                        ray |= bit(to);
+                        cur = to;
+                    }
+                    ray
+                })
+                .collect()
+        });
+        Tables {
+            size,
+            all: if size * size == Mask::BITS as usize { Mask::MAX } else { (1 << (size * size)) - 1 },
+            knight: offset_table(&KNIGHT_OFFSETS),
+            king: offset_table(&DIRECTIONS),
+            pawn: [offset_table(&[(-1, -1), (-1, 1)]), offset_table(&[(1, -1), (1, 1)])],
+            rays,
+        }
+    }
+
+    pub const fn index(&self, sq: Square) -> usize {
+        sq.i * self.size + sq.j
+    }
+
+    pub const fn bit(&self, sq: Square) -> Mask {
+        1 << self.index(sq)
+    }
+
+    pub const fn square(&self, idx: usize) -> Square {
+        Square::new(idx / self.size, idx % self.size)
+    }
+
+    // every square set in the mask, lowest bit first
+    pub fn squares(&self, mut mask: Mask) -> impl Iterator<Item = Square> + '_ {
+        std::iter::from_fn(move || {
+            if mask == 0 {
+                return None;
+            }
+            let idx = mask.trailing_zeros() as usize;
+            mask &= mask - 1;
+            Some(self.square(idx))
+        })
+    }
+
+    // whether the direction walks towards higher bits, which decides which end of the ray is nearest
+    const fn is_increasing(&self, dir: usize) -> bool {
+        let (di, dj) = DIRECTIONS[dir];
+        di as isize * self.size as isize + dj as isize > 0
+    }
+
+    // the blocker closest to the start of the ray
+    fn nearest(&self, dir: usize, blockers: Mask) -> usize {
+        if self.is_increasing(dir) {
+            blockers.trailing_zeros() as usize
+        } else {
+            (Mask::BITS - 1 - blockers.leading_zeros()) as usize
+        }
+    }
+
+    // Squares a slider on sq sees along the directions. Each ray stops at the first occupied square, which is included.
+    fn slide(&self, sq: usize, directions: &[usize], occupied: Mask) -> Mask {
+        let mut attacks = 0;
+        for &dir in directions {
+            let ray = self.rays[dir][sq];
+            let blockers = ray & occupied;
+            if blockers == 0 {
+                attacks |= ray;
+                continue;
+            }
+            attacks |= ray & !self.rays[dir][self.nearest(dir, blockers)];
+        }
+        attacks
+    }
+
+    pub fn rook_attacks(&self, sq: usize, occupied: Mask) -> Mask {
+        self.slide(sq, &[0, 1, 2, 3], occupied)
+    }
+
+    pub fn bishop_attacks(&self, sq: usize, occupied: Mask) -> Mask {
+        self.slide(sq, &[4, 5, 6, 7], occupied)
+    }
+
+    pub fn queen_attacks(&self, sq: usize, occupied: Mask) -> Mask {
+        self.rook_attacks(sq, occupied) | self.bishop_attacks(sq, occupied)
+    }
+
+    // Squares a piece on sq attacks. Pawns only attack their capture diagonals.
+    pub fn attacks(&self, sq: usize, color: Color, piece: Piece, occupied: Mask) -> Mask {
+        match piece {
+            Piece::Pawn => self.pawn[color as usize][sq],
+            Piece::Knight => self.knight[sq],
+            Piece::King => self.king[sq],
+            Piece::Rook => self.rook_attacks(sq, occupied),
+            Piece::Bishop => self.bishop_attacks(sq, occupied),
+            Piece::Queen => self.queen_attacks(sq, occupied),
+        }
+    }
+
+    // Squares the piece on sq can move to by its own rules, guarded or not.
+    // Stars and enemies stop sliders and can be landed on, walls stop them and can't be.
+    pub fn moves(&self, sq: usize, color: Color, piece: Piece, stars: Mask, enemies: Mask, walls: Mask) -> Mask {
+        let occupied = stars | enemies | walls;
+        if piece != Piece::Pawn {
+            return self.attacks(sq, color, piece, occupied) & !walls;
+        }
+        // single push, or double push from the starting rank. Pawns can push onto stars but can't capture forwards.
+        let size = self.size;
+        let forward = |mask: Mask| if color == Color::White { mask >> size } else { (mask << size) & self.all };
+        let empty = !occupied;
+        let one = forward(1 << sq) & (empty | stars);
+        let mut targets = one;
+        if sq / size == pawn_start_rank(size, color) {
+            targets |= forward(one & empty) & (empty | stars);
+        }
+        // diagonals are capture only
+        targets | self.pawn[color as usize][sq] & (stars | enemies)
+    }
+
+    // Squares guarded by the pieces in the masks, indexed by piece. Only they and the walls block their attacks.
+    pub fn guarded(&self, enemies: &[Mask; 6], walls: Mask) -> Mask {
+        let occupied = enemies.iter().fold(walls, |mask, pieces| mask | pieces);
+        let mut attacked = 0;
+        for piece in PIECES {
+            for sq in self.squares(enemies[piece as usize]) {
+                attacked |= self.attacks(self.index(sq), Color::Black, piece, occupied);
+            }
+        }
+        attacked
+    }
+}
+
+// The board as bitmasks, one per kind of cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitBoard {
+    pub tables: &'static Tables,
+    pub stars: Mask,
+    pub obstacles: Mask,
+    // indexed by colour then piece
+    pub pieces: [[Mask; 6]; 2],
+    pub num_star_cnt: i8,
+    pub promotion_piece: Piece,
+}
+
+const PIECES: [Piece; 6] = [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen, Piece::King];
+const COLORS: [Color; 2] = [Color::White, Color::Black];
+
+impl BitBoard {
+    pub fn color_mask(&self, color: Color) -> Mask {
+        self.pieces[color as usize].iter().fold(0, |mask, pieces| mask | pieces)
+    }
+
+    pub fn occupied(&self) -> Mask {
+        self.stars | self.obstacles | self.color_mask(Color::White) | self.color_mask(Color::Black)
+    }
+
+    // Squares guarded by the enemy pieces. Only enemies and obstacles block enemy attacks.
+    pub fn attacked(&self) -> Mask {
+        self.tables.guarded(&self.pieces[Color::Black as usize], self.obstacles)
+    }
+
+    /// Checks to see if we are jumping over a star or another piece, and finds the first one in the way.
+    /// Stars and enemies on the goal square don't block since they can be taken.
+    pub fn blocking_square(&self, m_piece: &MovePiece) -> Option<Square> {
+        let t = self.tables;
+        let di = (m_piece.to.i as i8 - m_piece.from.i as i8).signum();
+        let dj = (m_piece.to.j as i8 - m_piece.from.j as i8).signum();
+        let dir = DIRECTIONS.iter().position(|&d| d == (di, dj))?;
+        let from = t.index(m_piece.from);
+        let to = t.index(m_piece.to);
+        // only straight lines and diagonals have squares in between
+        if t.rays[dir][from] & t.bit(m_piece.to) == 0 {
+            return None;
+        }
+        let between = t.rays[dir][from] & !t.rays[dir][to] & !t.bit(m_piece.to);
+        let blockers = between & self.occupied();
+        if blockers != 0 {
+            return Some(t.square(t.nearest(dir, blockers)));
+        }
+        let goal = self.cell(m_piece.to);
+        (goal != Cell::Empty && !goal.is_capturable()).then_some(m_piece.to)
+    }
+}
+
+impl BitBoard {
+    pub fn size(&self) -> usize {
+        self.tables.size
+    }
+
+    // what's on the square, in the LiBoard's terms
+    pub fn cell(&self, sq: Square) -> Cell {
+        let mask = self.tables.bit(sq);
+        if self.stars & mask != 0 {
+            return Cell::Star;
+        }
+        if self.obstacles & mask != 0 {
+            return Cell::Obstacle;
+        }
+        for color in COLORS {
+            for piece in PIECES {
+                if self.pieces[color as usize][piece as usize] & mask != 0 {
+                    return Cell::Piece(color, piece);
+                }
+            }
+        }
+        Cell::Empty
+    }
+}
+
+impl From<&LiBoard> for BitBoard {
+    fn from(board: &LiBoard) -> BitBoard {
+        let tables = tables(board.size());
+        let mut bitboard = BitBoard {
+            tables,
+            stars: 0,
+            obstacles: 0,
+            pieces: [[0; 6]; 2],
+            num_star_cnt: board.num_star_cnt,
+            promotion_piece: board.promotion_piece,
+        };
+        for sq in board.squares() {
+            match board[sq] {
+                Cell::Empty => (),
+                Cell::Star => bitboard.stars |= tables.bit(sq),
+                Cell::Obstacle => bitboard.obstacles |= tables.bit(sq),
+                Cell::Piece(color, piece) => bitboard.pieces[color as usize][piece as usize] |= tables.bit(sq),
+            }
+        }
+        bitboard
+    }
+}
+
+impl From<&BitBoard> for LiBoard {
+    fn from(bitboard: &BitBoard) -> LiBoard {
+        let mut board = LiBoard {
+            board: vec![vec![Cell::Empty; bitboard.size()]; bitboard.size()],
+            // the player's pieces are all the white ones
+            pieces: bitboard.tables.squares(bitboard.color_mask(Color::White)).collect(),
+            num_star_cnt: bitboard.num_star_cnt,
+            promotion_piece: bitboard.promotion_piece,
+        };
+        for sq in bitboard.tables.squares(bitboard.tables.all) {
+            board[sq] = bitboard.cell(sq);
+        }
+        board
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitboard_round_trip() {
+        for seed in 0..10 {
+            let board = LiBoard::from_options_seeded(
+                &BoardOptions {
+                    star_cnt: 8,
+                    enemy_cnt: 4,
+                    obstacle_cnt: 4,
+                    ..Default::default()
+                },
+                seed,
+            );
+            let bitboard = BitBoard::from(&board);
+            assert_eq!(bitboard.stars.count_ones(), 8);
+            assert_eq!(bitboard.obstacles.count_ones(), 4);
+            assert_eq!(bitboard.color_mask(Color::Black).count_ones(), 4);
+            let back = LiBoard::from(&bitboard);
+            assert_eq!(board.board, back.board);
+            assert_eq!(board.pieces, back.pieces);
+        }
+    }
+
+    #[test]
+    fn test_bitboard_tables() {
+        let t = tables(8);
+        // knight in the corner, king on the edge
+        assert_eq!(t.knight[t.index(Square::new(7, 0))], t.bit(Square::new(5, 1)) | t.bit(Square::new(6, 2)));
+        assert_eq!(t.king[t.index(Square::new(0, 4))].count_ones(), 5);
+        // a rook on d4 sees up to and including the first blocker on each side
+        let occupied = t.bit(Square::new(4, 1)) | t.bit(Square::new(1, 3));
+        let attacks = t.rook_attacks(t.index(Square::new(4, 3)), occupied);
+        assert_eq!(attacks.count_ones(), 2 + 4 + 3 + 3);
+        assert!(attacks & t.bit(Square::new(4, 1)) != 0);
+        assert!(attacks & t.bit(Square::new(4, 0)) == 0);
+        assert!(attacks & t.bit(Square::new(0, 3)) == 0);
+        assert_eq!(t.bishop_attacks(t.index(Square::new(0, 0)), 0).count_ones(), 7);
+        // rays stop at the edge of smaller and bigger boards too
+        assert_eq!(tables(5).queen_attacks(0, 0).count_ones(), 12);
+        assert_eq!(tables(10).bishop_attacks(0, 0).count_ones(), 9);
+        assert_eq!(tables(10).all.count_ones(), 100);
+    }
+
+    #[test]
+    fn test_bitboard_matches_board() {
+        for size in BOARD_SIZES {
+            for piece in [Piece::Queen, Piece::Knight, Piece::Rook, Piece::Bishop, Piece::King, Piece::Pawn] {
+                for seed in 0..10 {
+                    let limits = CountLimits::for_size(size);
+                    let board = LiBoard::from_options_seeded(
+                        &BoardOptions {
+                            size,
+                            star_cnt: 6.min(limits.stars),
+                            choice_piece: piece,
+                            enemy_cnt: limits.enemies,
+                            obstacle_cnt: limits.obstacles,
+                            ..Default::default()
+                        },
+                        seed,
+                    );
+                    let bitboard = BitBoard::from(&board);
+                    let t = bitboard.tables;
+                    let attacked = bitboard.attacked();
+                    for sq in board.squares() {
+                        assert_eq!(board.is_attacked(sq), attacked & t.bit(sq) != 0, "{:?} {:?}", sq, board);
+                    }
+                }
+            }
+        }
+    }
+}
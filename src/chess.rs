@@ -1,469 +1,1198 @@
+use crate::bitboard::BitBoard;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::{Index, IndexMut, RangeInclusive};
 
-pub const PAWN_WHITE: i8 = 2;
-pub const PAWN_BLACK: i8 = 1;
-pub const ROOK_WHITE: i8 = 10;
-pub const ROOK_BLACK: i8 = 5;
-pub const KNIGHT_WHITE: i8 = 11;
-pub const KNIGHT_BLACK: i8 = 4;
-pub const QUEEN_WHITE: i8 = 13;
-pub const QUEEN_BLACK: i8 = 6;
-pub const BISHOP_WHITE: i8 = 12;
-pub const BISHOP_BLACK: i8 = 3;
-pub const KING_BLACK: i8 = 7;
-pub const KING_WHITE: i8 = 14;
-pub const STAR_VALUE: i8 = 99;
-#[derive(Debug, Clone)]
-pub struct MovePiece {
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Color {
+    White,
+    Black,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Piece {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+impl fmt::Display for Piece {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Piece::Pawn => "pawn",
+            Piece::Knight => "knight",
+            Piece::Bishop => "bishop",
+            Piece::Rook => "rook",
+            Piece::Queen => "queen",
+            Piece::King => "king",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// What sits on a board square
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Cell {
+    #[default]
+    Empty,
+    Star,
+    // Walls that nothing can land on or move through
+    Obstacle,
+    Piece(Color, Piece),
+}
+
+impl Cell {
+    // The player always plays white, so black pieces are enemies
+    pub const fn is_enemy(self) -> bool {
+        matches!(self, Cell::Piece(Color::Black, _))
+    }
+
+    // Stars can be collected and enemies captured, everything else blocks
+    pub const fn is_capturable(self) -> bool {
+        matches!(self, Cell::Star) || self.is_enemy()
+    }
+}
+
+// A board coordinate, row i from the top and column j from the left
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Square {
     pub i: usize,
     pub j: usize,
-    pub goal_i: usize,
-    pub goal_j: usize,
 }
 
-pub enum MoveStatus {
-    Valid,
-    Invalid,
+impl Square {
+    pub const fn new(i: usize, j: usize) -> Square {
+        Square { i, j }
+    }
+
+    pub const fn in_bounds(self, size: usize) -> bool {
+        self.i < size && self.j < size
+    }
+
+    // Chess names like e4, with row 0 as the last rank
+    pub fn name(self, size: usize) -> String {
+        format!("{}{}", (b'a' + self.j as u8) as char, size - self.i)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MovePiece {
+    pub from: Square,
+    pub to: Square,
+}
+
+// Why a move was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    SameSquare,
+    OutOfBounds,
+    // nothing the player can move on the starting square
+    NoPiece,
+    // enemies never move
+    NotYourPiece,
+    // something in the way, or a wall on the goal square
+    Blocked(Square),
+    // another of the player's pieces is on the goal square
+    Occupied(Square),
+    // the piece doesn't move like that
+    IllegalGeometry(Piece),
+    // the goal square is guarded by the enemy on this square
+    Attacked(Square),
 }
 
-impl MoveStatus {
-    #[inline]
-    pub const fn is_valid(&self) -> bool {
+impl MoveError {
+    // the square to point the player at, if there's one
+    pub const fn square(&self) -> Option<Square> {
+        match self {
+            MoveError::Blocked(sq) | MoveError::Occupied(sq) | MoveError::Attacked(sq) => Some(*sq),
+            _ => None,
+        }
+    }
+
+    // what to tell the player, square names depend on the board size
+    pub fn message(&self, size: usize) -> String {
         match self {
-            Self::Valid => true,
-            Self::Invalid => false,
+            MoveError::SameSquare => "The piece has to move".to_owned(),
+            MoveError::OutOfBounds => "That square is off the board".to_owned(),
+            MoveError::NoPiece => "There's no piece to move there".to_owned(),
+            MoveError::NotYourPiece => "Enemy pieces can't be moved".to_owned(),
+            MoveError::Blocked(sq) => format!("The way is blocked on {}", sq.name(size)),
+            MoveError::Occupied(sq) => format!("Your own piece is on {}", sq.name(size)),
+            MoveError::IllegalGeometry(piece) => format!("A {} can't move like that", piece),
+            MoveError::Attacked(sq) => format!("That square is guarded by the piece on {}", sq.name(size)),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// Board sizes the player can pick. Bigger boards don't fit in a bitboard.
+pub const BOARD_SIZES: [usize; 4] = [5, 6, 8, 10];
+pub const MAX_BOARD_SIZE: usize = 11;
+
+// The most pieces the player can have in one puzzle
+pub const MAX_PLAYER_PIECES: usize = 3;
+
+// Pawns can double push from the second rank on their side
+pub const fn pawn_start_rank(size: usize, color: Color) -> usize {
+    match color {
+        Color::White => size - 2,
+        Color::Black => 1,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct LiBoard {
-    // 8x8 board
-    pub board: [[i8; 8]; 8],
-    // Position of the player moving piece
-    pub main_piece: (i8, i8),
+    // square board, rows from the top
+    pub board: Vec<Vec<Cell>>,
+    // Where the player's pieces are, kept up to date as they move
+    pub pieces: Vec<Square>,
     // How many stars on the board at the start
     pub num_star_cnt: i8,
+    // What a pawn turns into when it reaches the last rank
+    #[serde(default = "default_promotion_piece")]
+    pub promotion_piece: Piece,
 }
 
-impl LiBoard {
-    // set up board randomly with n stars and choice piece
-    pub fn new(star_cnt: i8, choice_piece: i8) -> LiBoard {
-        let mut b = [[0; 8]; 8];
-        let mut star_pairs = Vec::new();
-        let mut already_added_stars = HashMap::new();
-
-        let main_piece_i = rand::random::<u8>() % 8;
-        let main_piece_j = rand::random::<u8>() % 8;
-        already_added_stars.insert((main_piece_i, main_piece_j), 0);
-        for _v in 0..star_cnt {
-            let mut sample = (rand::random::<u8>() % 8, rand::random::<u8>() % 8);
-            while already_added_stars.contains_key(&sample) {
-                sample = (rand::random::<u8>() % 8, rand::random::<u8>() % 8);
-            }
-            already_added_stars.insert(sample, 0);
-            star_pairs.push(sample);
+fn default_promotion_piece() -> Piece {
+    Piece::Queen
+}
+
+impl Index<Square> for LiBoard {
+    type Output = Cell;
+
+    fn index(&self, sq: Square) -> &Cell {
+        &self.board[sq.i][sq.j]
+    }
+}
+
+impl IndexMut<Square> for LiBoard {
+    fn index_mut(&mut self, sq: Square) -> &mut Cell {
+        &mut self.board[sq.i][sq.j]
+    }
+}
+
+// How many boards a difficulty target gets before settling for the closest one
+const MAX_TARGET_TRIES: usize = 40;
+
+// Search states all the tries at a difficulty target get to look at together, and the most one board gets.
+// Boards with several pieces can take minutes to solve, so those are skipped rather than waited on.
+const TARGET_SEARCH_BUDGET: usize = 100_000;
+const BOARD_SEARCH_BUDGET: usize = 40_000;
+
+// Search states the full solvability check gets before a board is thrown away as too slow to check
+const SOLVABLE_CHECK_BUDGET: usize = 100_000;
+
+// Hard boards have at most this many optimal routes
+const HARD_MAX_ROUTES: u64 = 12;
+
+// Enemy pieces that can be placed on the board. They never move, they just guard squares.
+const ENEMY_PIECES: [Piece; 4] = [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook];
+
+// Settings used to generate a random board
+#[derive(Debug, Clone)]
+pub struct BoardOptions {
+    // Width and height of the board
+    pub size: usize,
+    // With a target this is only where the star count starts from
+    pub star_cnt: i8,
+    // The player's piece, always white
+    pub choice_piece: Piece,
+    // More pieces for the player to collect stars with
+    pub extra_pieces: Vec<Piece>,
+    // What a pawn turns into when it reaches the last rank
+    pub promotion_piece: Piece,
+    // How many enemy pieces guard the board
+    pub enemy_cnt: i8,
+    // How many walls block the board
+    pub obstacle_cnt: i8,
+    // How hard the board should be. The star count is changed until the board gets there.
+    pub target: Option<DifficultyTarget>,
+}
+
+// What a generated board's optimal solution should look like
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DifficultyTarget {
+    // how many moves the optimal solution takes
+    pub moves: RangeInclusive<i8>,
+    // at most this many different optimal routes, so the best one is harder to stumble on
+    pub max_routes: Option<u64>,
+}
+
+impl DifficultyTarget {
+    // whether a board with this optimal solution hits the target
+    pub fn is_met(&self, move_cnt: i8, route_cnt: u64) -> bool {
+        self.moves.contains(&move_cnt) && self.max_routes.map_or(true, |max| route_cnt <= max)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    // Star count to start generating from. It gets there quicker the closer this is.
+    pub fn star_cnt(self) -> i8 {
+        match self {
+            Difficulty::Easy => 3,
+            Difficulty::Medium => 6,
+            Difficulty::Hard => 10,
         }
+    }
 
-        for i in 0..8 {
-            for j in 0..8 {
-                if i == main_piece_i && j == main_piece_j {
-                    b[i as usize][j as usize] = choice_piece;
-                } else {
-                    b[i as usize][j as usize] = 0;
-                }
-            }
+    pub fn target(self) -> DifficultyTarget {
+        match self {
+            Difficulty::Easy => DifficultyTarget { moves: 3..=5, max_routes: None },
+            Difficulty::Medium => DifficultyTarget { moves: 7..=10, max_routes: None },
+            Difficulty::Hard => DifficultyTarget {
+                moves: 12..=16,
+                max_routes: Some(HARD_MAX_ROUTES),
+            },
         }
+    }
+}
 
-        for i in 0..star_cnt {
-            b[star_pairs[i as usize].0 as usize][star_pairs[i as usize].1 as usize] = STAR_VALUE;
+impl Default for BoardOptions {
+    fn default() -> Self {
+        Self {
+            size: 8,
+            star_cnt: 5,
+            choice_piece: Piece::Queen,
+            extra_pieces: Vec::new(),
+            promotion_piece: default_promotion_piece(),
+            enemy_cnt: 0,
+            obstacle_cnt: 0,
+            target: None,
         }
+    }
+}
+
+// How many of each thing the side panel allows on a board of a size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CountLimits {
+    pub stars: i8,
+    pub enemies: i8,
+    pub obstacles: i8,
+}
 
-        LiBoard {
-            board: b,
-            main_piece: (main_piece_i as i8, main_piece_j as i8),
-            num_star_cnt: star_cnt as i8,
+impl CountLimits {
+    // Scaled by area from the 8x8 limits. Stars don't scale up since bigger boards are slow enough to solve already.
+    pub fn for_size(size: usize) -> CountLimits {
+        let scale = |limit: usize| (limit * size * size / 64).max(1) as i8;
+        CountLimits {
+            stars: scale(18).min(18),
+            enemies: scale(4),
+            obstacles: scale(8),
         }
     }
+}
+
+impl LiBoard {
+    // set up board randomly with n stars and choice piece
+    pub fn new(star_cnt: i8, choice_piece: Piece) -> LiBoard {
+        Self::from_seed(rand::random(), star_cnt, choice_piece)
+    }
 
-    /// Checks to see if we are jumping over a star
-    pub fn is_jumping_over_piece(&self, m_piece: &MovePiece) -> bool {
-        // get start piece pos
-        let i = m_piece.i as i8;
-        let j = m_piece.j as i8;
+    // Same as new, but the same seed always sets up the same board
+    pub fn from_seed(seed: u64, star_cnt: i8, choice_piece: Piece) -> LiBoard {
+        Self::from_options_seeded(
+            &BoardOptions {
+                star_cnt,
+                choice_piece,
+                ..Default::default()
+            },
+            seed,
+        )
+    }
 
-        // determine movement type
-        // vertical
-        if (m_piece.goal_i as i8 - i).abs() == 0 {
-            if j < m_piece.goal_j as i8 {
-                let mut start = j + 1;
-                while start <= m_piece.goal_j as i8 {
-                    if self.board[i as usize][start as usize] != 0 {
-                        if start as usize == m_piece.goal_j && self.board[m_piece.goal_i][m_piece.goal_j] == STAR_VALUE {
-                            return false;
-                        }
-                        return true;
-                    }
-                    start += 1;
-                }
-                false
+    // Set up board randomly from the given options. Every generated board is solvable.
+    // The same seed and options always set up the same board.
+    pub fn from_options_seeded(options: &BoardOptions, seed: u64) -> LiBoard {
+        // ChaCha gives the same numbers on every platform and rand version, unlike StdRng
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        match &options.target {
+            Some(target) => Self::generate_targeted(options, target, &mut rng),
+            None => Self::generate_solvable(options, &mut rng),
+        }
+    }
+
+    // Adds a star when the optimal solution is too short and takes one away when it's too long.
+    // Gives up after a while with the board that came closest, since some settings can't reach the target.
+    fn generate_targeted(options: &BoardOptions, target: &DifficultyTarget, rng: &mut ChaCha8Rng) -> LiBoard {
+        let max_stars = CountLimits::for_size(options.size).stars;
+        let mut star_cnt = options.star_cnt.clamp(1, max_stars);
+        let mut closest: Option<(i8, LiBoard)> = None;
+        let mut budget = TARGET_SEARCH_BUDGET;
+        for _ in 0..MAX_TARGET_TRIES {
+            if budget == 0 {
+                break;
+            }
+            let board = Self::generate_solvable(&BoardOptions { star_cnt, ..options.clone() }, rng);
+            let board_budget = budget.min(BOARD_SEARCH_BUDGET);
+            let mut left = board_budget;
+            let solved = board.optimal_route_count_within(&mut left);
+            budget -= board_budget - left;
+            // the board is solvable, so no count means it ran out of budget and fewer stars would be quicker
+            let Some((move_cnt, route_cnt)) = solved else {
+                star_cnt = (star_cnt - 1).max(1);
+                continue;
+            };
+            let miss = if move_cnt < *target.moves.start() {
+                star_cnt = (star_cnt + 1).min(max_stars);
+                target.moves.start() - move_cnt
+            } else if move_cnt > *target.moves.end() {
+                star_cnt = (star_cnt - 1).max(1);
+                move_cnt - target.moves.end()
+            } else if !target.is_met(move_cnt, route_cnt) {
+                // right length but too many ways to get there
+                1
             } else {
-                let mut start = j - 1;
-                while start >= m_piece.goal_j as i8 {
-                    if self.board[i as usize][start as usize] != 0 {
-                        if start == m_piece.goal_j as i8 && self.board[m_piece.goal_i][m_piece.goal_j] == STAR_VALUE {
-                            return false;
-                        }
-                        return true;
-                    }
-                    start -= 1;
-                }
-                false
+                return board;
+            };
+            if closest.as_ref().map_or(true, |(best, _)| miss < *best) {
+                closest = Some((miss, board));
             }
         }
-        // horizontal
-        else if (m_piece.goal_j as i8 - j).abs() == 0 {
-            if i < m_piece.goal_i as i8 {
-                let mut start = i + 1;
-                while start <= m_piece.goal_i as i8 {
-                    if self.board[start as usize][j as usize] != 0 {
-                        if start == m_piece.goal_i as i8 && self.board[m_piece.goal_i][m_piece.goal_j] == STAR_VALUE {
-                            return false;
-                        }
-                        return true;
-                    }
-                    start += 1;
-                }
-                false
-            } else {
-                let mut start = i - 1;
-                while start >= m_piece.goal_i as i8 {
-                    if self.board[start as usize][j as usize] != 0 {
-                        if start == m_piece.goal_i as i8 && self.board[m_piece.goal_i][m_piece.goal_j] == STAR_VALUE {
-                            return false;
-                        }
-                        return true;
-                    }
-                    start -= 1;
-                }
-                false
+        match closest {
+            Some((_, board)) => board,
+            None => Self::generate_solvable(options, rng),
+        }
+    }
+
+    // a random board that can be cleared
+    fn generate_solvable(options: &BoardOptions, rng: &mut ChaCha8Rng) -> LiBoard {
+        loop {
+            // crowded small boards can run out of room, just try again
+            let Some(board) = Self::generate(options, rng) else {
+                continue;
+            };
+            // Without enemies stars are only placed where the piece can get to.
+            // Enemies, obstacles and the player's other pieces can wall off parts of the board, so check those boards can be cleared.
+            // The greedy check can miss pawn routes, which a single piece is usually quick enough to fully solve.
+            // Boards that run out of budget get thrown away like unsolvable ones.
+            let is_open = options.enemy_cnt == 0 && options.obstacle_cnt == 0 && options.extra_pieces.is_empty();
+            let is_solvable = || {
+                let mut budget = SOLVABLE_CHECK_BUDGET;
+                board.optimal_route_count_within(&mut budget).is_some()
+            };
+            if is_open || board.is_greedy_solvable() || (options.extra_pieces.is_empty() && is_solvable()) {
+                return board;
             }
         }
-        // everything else (diagonals)
-        else if (j < m_piece.goal_j as i8) && (i < m_piece.goal_i as i8) {
-            let mut temp = i + 1;
-            let mut start = j + 1;
-            while start <= m_piece.goal_j as i8 {
-                if self.board[temp as usize][start as usize] != 0 {
-                    if start == m_piece.goal_j as i8 && self.board[m_piece.goal_i][m_piece.goal_j] == STAR_VALUE {
-                        return false;
-                    }
-                    return true;
-                }
-                temp += 1;
-                start += 1;
+    }
+
+    fn generate(options: &BoardOptions, rng: &mut ChaCha8Rng) -> Option<LiBoard> {
+        let BoardOptions {
+            size,
+            star_cnt,
+            promotion_piece,
+            enemy_cnt,
+            obstacle_cnt,
+            ..
+        } = *options;
+        let mut liboard = LiBoard {
+            board: vec![vec![Cell::Empty; size]; size],
+            pieces: Vec::new(),
+            num_star_cnt: star_cnt,
+            promotion_piece,
+        };
+
+        // a random empty square that passes the check, None if there are none left
+        let random_free_square = |board: &LiBoard, rng: &mut ChaCha8Rng, is_allowed: &dyn Fn(Square) -> bool| {
+            let free: Vec<Square> = board.squares().filter(|&sq| board[sq] == Cell::Empty && is_allowed(sq)).collect();
+            (!free.is_empty()).then(|| free[rng.gen_range(0..free.len())])
+        };
+
+        // Bishops never leave their square colour, so stars have to stay on a colour one of them is on.
+        // A white pawn promoting to a bishop can always push straight up its file, so use that promotion square's colour.
+        let mut reachable_colours = Vec::new();
+        let mut any_colour = false;
+        for &piece in [options.choice_piece].iter().chain(&options.extra_pieces) {
+            // pawns can't start on the promotion rank or behind their starting rank
+            let sq = random_free_square(&liboard, rng, &|sq| piece != Piece::Pawn || (1..size - 1).contains(&sq.i))?;
+            liboard[sq] = Cell::Piece(Color::White, piece);
+            liboard.pieces.push(sq);
+            match (piece, promotion_piece) {
+                (Piece::Bishop, _) => reachable_colours.push((sq.i + sq.j) % 2),
+                (Piece::Pawn, Piece::Bishop) => reachable_colours.push(sq.j % 2),
+                _ => any_colour = true,
             }
-            false
-        } else if (j < m_piece.goal_j as i8) && (i > m_piece.goal_i as i8) {
-            let mut temp = i - 1;
-            let mut start = j + 1;
-            while start <= m_piece.goal_j as i8 {
-                if self.board[temp as usize][start as usize] != 0 {
-                    if start == m_piece.goal_j as i8 && self.board[m_piece.goal_i][m_piece.goal_j] == STAR_VALUE {
-                        return false;
-                    }
-                    return true;
-                }
-                temp -= 1;
-                start += 1;
+        }
+
+        for _v in 0..enemy_cnt {
+            let sq = random_free_square(&liboard, rng, &|_| true)?;
+            liboard[sq] = Cell::Piece(Color::Black, ENEMY_PIECES[rng.gen_range(0..ENEMY_PIECES.len())]);
+        }
+
+        for _v in 0..obstacle_cnt {
+            let sq = random_free_square(&liboard, rng, &|_| true)?;
+            liboard[sq] = Cell::Obstacle;
+        }
+
+        // stars on guarded squares could never be collected
+        let guarded: Vec<bool> = liboard.squares().map(|sq| liboard.is_attacked(sq)).collect();
+        let star_allowed = |sq: Square| {
+            let colour_ok = any_colour || reachable_colours.contains(&((sq.i + sq.j) % 2));
+            colour_ok && !guarded[sq.i * size + sq.j]
+        };
+        for _v in 0..star_cnt {
+            let sq = random_free_square(&liboard, rng, &star_allowed)?;
+            liboard[sq] = Cell::Star;
+        }
+        Some(liboard)
+    }
+
+    // Width and height of the board
+    pub fn size(&self) -> usize {
+        self.board.len()
+    }
+
+    // every square on the board, row by row
+    pub fn squares(&self) -> impl Iterator<Item = Square> {
+        let size = self.size();
+        (0..size * size).map(move |idx| Square::new(idx / size, idx % size))
+    }
+
+    // Checks if any enemy piece guards the square
+    pub fn is_attacked(&self, sq: Square) -> bool {
+        self.attacker(sq).is_some()
+    }
+
+    // Finds an enemy piece guarding the square.
+    // The player's own pieces and stars don't block enemy attacks, only other enemies and obstacles do.
+    pub fn attacker(&self, sq: Square) -> Option<Square> {
+        self.squares().find(|&from| {
+            let Cell::Piece(Color::Black, enemy) = self[from] else {
+                return false;
+            };
+            if from == sq {
+                return false;
             }
-            false
-        } else if (j > m_piece.goal_j as i8) && (i < m_piece.goal_i as i8) {
-            let mut temp = i + 1;
-            let mut start = j - 1;
-            while start >= m_piece.goal_j as i8 {
-                if self.board[temp as usize][start as usize] != 0 {
-                    if start == m_piece.goal_j as i8 && self.board[m_piece.goal_i][m_piece.goal_j] == STAR_VALUE {
-                        return false;
-                    }
-                    return true;
-                }
-                temp += 1;
-                start -= 1;
+            let di = sq.i as i8 - from.i as i8;
+            let dj = sq.j as i8 - from.j as i8;
+            let is_straight = di == 0 || dj == 0;
+            let is_diagonal = di.abs() == dj.abs();
+            match enemy {
+                Piece::Pawn => di == 1 && dj.abs() == 1,
+                Piece::Knight => (di.abs() == 2 && dj.abs() == 1) || (di.abs() == 1 && dj.abs() == 2),
+                Piece::King => di.abs() <= 1 && dj.abs() <= 1,
+                Piece::Rook => is_straight && !self.is_blocked_between(from, sq),
+                Piece::Bishop => is_diagonal && !self.is_blocked_between(from, sq),
+                Piece::Queen => (is_straight || is_diagonal) && !self.is_blocked_between(from, sq),
             }
-            false
-        } else {
-            let mut temp = i - 1;
-            let mut start = j - 1;
-            while start >= m_piece.goal_j as i8 {
-                if self.board[temp as usize][start as usize] != 0 {
-                    if start == m_piece.goal_j as i8 && self.board[m_piece.goal_i][m_piece.goal_j] == STAR_VALUE {
-                        return false;
-                    }
-                    return true;
-                }
-                temp -= 1;
-                start -= 1;
+        })
+    }
+
+    // Checks if an enemy piece or obstacle sits strictly between two squares on the same line
+    fn is_blocked_between(&self, from: Square, to: Square) -> bool {
+        let step_i = (to.i as i8 - from.i as i8).signum();
+        let step_j = (to.j as i8 - from.j as i8).signum();
+        let mut cur = (from.i as i8 + step_i, from.j as i8 + step_j);
+        while cur != (to.i as i8, to.j as i8) {
+            let cell = self.board[cur.0 as usize][cur.1 as usize];
+            if cell.is_enemy() || cell == Cell::Obstacle {
+                return true;
             }
-            false
+            cur = (cur.0 + step_i, cur.1 + step_j);
         }
+        false
     }
-    pub fn validate_move_rook(&self, m_piece: &MovePiece) -> MoveStatus {
-        // make sure goal_i , goal_j reaches the first blocking piece
-        let i = m_piece.i as i8;
-        let j = m_piece.j as i8;
 
-        if (((m_piece.goal_i as i8 - i).abs() == 0) || ((m_piece.goal_j as i8 - j).abs() == 0)) && !self.is_jumping_over_piece(m_piece) {
-            return MoveStatus::Valid;
+    /// Checks to see if we are jumping over a star or another piece, and finds the first one in the way.
+    /// Stars and enemies on the goal square don't block since they can be taken.
+    pub fn blocking_square(&self, m_piece: &MovePiece) -> Option<Square> {
+        BitBoard::from(self).blocking_square(m_piece)
+    }
+
+    // sliding pieces need a line to the goal and nothing in the way
+    fn validate_slide(&self, m_piece: &MovePiece, piece: Piece, is_line: bool) -> Result<(), MoveError> {
+        if !is_line {
+            return Err(MoveError::IllegalGeometry(piece));
+        }
+        match self.blocking_square(m_piece) {
+            Some(sq) => Err(MoveError::Blocked(sq)),
+            None => Ok(()),
         }
-        MoveStatus::Invalid
     }
-    pub fn validate_move_bishop(&self, m_piece: &MovePiece) -> MoveStatus {
-        let i = m_piece.i as i8;
-        let j = m_piece.j as i8;
-        // part one of validity
-        if (m_piece.goal_i as i8 - i).abs() == (m_piece.goal_j as i8 - j).abs() && !self.is_jumping_over_piece(m_piece) {
-            return MoveStatus::Valid;
+
+    pub fn validate_move_rook(&self, m_piece: &MovePiece) -> Result<(), MoveError> {
+        // make sure goal_i , goal_j reaches the first blocking piece
+        let is_straight = m_piece.to.i == m_piece.from.i || m_piece.to.j == m_piece.from.j;
+        self.validate_slide(m_piece, Piece::Rook, is_straight)
+    }
+    pub fn validate_move_bishop(&self, m_piece: &MovePiece) -> Result<(), MoveError> {
+        let is_diagonal = m_piece.to.i.abs_diff(m_piece.from.i) == m_piece.to.j.abs_diff(m_piece.from.j);
+        self.validate_slide(m_piece, Piece::Bishop, is_diagonal)
+    }
+    pub fn validate_move_queen(&self, m_piece: &MovePiece) -> Result<(), MoveError> {
+        let is_straight = m_piece.to.i == m_piece.from.i || m_piece.to.j == m_piece.from.j;
+        let is_diagonal = m_piece.to.i.abs_diff(m_piece.from.i) == m_piece.to.j.abs_diff(m_piece.from.j);
+        self.validate_slide(m_piece, Piece::Queen, is_straight || is_diagonal)
+    }
+
+    pub fn validate_move_knight(&self, m_piece: &MovePiece) -> Result<(), MoveError> {
+        let di = m_piece.to.i.abs_diff(m_piece.from.i);
+        let dj = m_piece.to.j.abs_diff(m_piece.from.j);
+        if (di == 2 && dj == 1) || (di == 1 && dj == 2) {
+            return Ok(());
         }
-        MoveStatus::Invalid
+        Err(MoveError::IllegalGeometry(Piece::Knight))
     }
-    pub fn validate_move_queen(&self, m_piece: &MovePiece) -> MoveStatus {
-        let i = m_piece.i as i8;
-        let j = m_piece.j as i8;
-        // part one of validity
-        if (((m_piece.goal_i as i8 - i).abs() == 0) || ((m_piece.goal_j as i8 - j).abs() == 0) || ((m_piece.goal_i as i8 - i).abs() == (m_piece.goal_j as i8 - j).abs()))
-            && !self.is_jumping_over_piece(m_piece)
-        {
-            return MoveStatus::Valid;
+
+    pub fn validate_move_king(&self, m_piece: &MovePiece) -> Result<(), MoveError> {
+        // one square in any direction
+        if m_piece.to.i.abs_diff(m_piece.from.i) <= 1 && m_piece.to.j.abs_diff(m_piece.from.j) <= 1 {
+            return Ok(());
         }
-        MoveStatus::Invalid
+        Err(MoveError::IllegalGeometry(Piece::King))
     }
 
-    pub fn validate_move_knight(&self, m_piece: &MovePiece) -> MoveStatus {
-        let i = m_piece.i as i8;
-        let j = m_piece.j as i8;
-        if ((m_piece.goal_i as i8 - i).abs() == 2 && (m_piece.goal_j as i8 - j).abs() == 1) || ((m_piece.goal_i as i8 - i).abs() == 1 && (m_piece.goal_j as i8 - j).abs() == 2) {
-            return MoveStatus::Valid;
+    pub fn validate_move_pawn(&self, m_piece: &MovePiece) -> Result<(), MoveError> {
+        let i = m_piece.from.i as i8;
+        let j = m_piece.from.j as i8;
+        // white pawns move up the board, black pawns move down
+        let color = if self[m_piece.from] == Cell::Piece(Color::White, Piece::Pawn) {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let dir = if color == Color::White { -1 } else { 1 };
+        let start_rank = pawn_start_rank(self.size(), color) as i8;
+        let di = m_piece.to.i as i8 - i;
+        let dj = m_piece.to.j as i8 - j;
+
+        // single push, or double push from the starting rank. Pawns can't capture forwards.
+        if dj == 0 && (di == dir || (di == 2 * dir && i == start_rank)) {
+            if self[m_piece.to].is_enemy() {
+                return Err(MoveError::Blocked(m_piece.to));
+            }
+            return match self.blocking_square(m_piece) {
+                Some(sq) => Err(MoveError::Blocked(sq)),
+                None => Ok(()),
+            };
+        }
+        // diagonals are capture only
+        if di == dir && dj.abs() == 1 && self[m_piece.to].is_capturable() {
+            return Ok(());
         }
-        MoveStatus::Invalid
+        Err(MoveError::IllegalGeometry(Piece::Pawn))
     }
 
-    pub fn validate_move(&self, m_piece: &MovePiece) -> MoveStatus {
+    pub fn validate_move(&self, m_piece: &MovePiece) -> Result<(), MoveError> {
         // leap of faith
         // if the piece that we are trying to move exists
-        if m_piece.i == m_piece.goal_i && m_piece.j == m_piece.goal_j {
-            return MoveStatus::Invalid;
+        if m_piece.from == m_piece.to {
+            return Err(MoveError::SameSquare);
         }
         // check for out of bounds
-        if m_piece.goal_i >= 8 || m_piece.goal_j >= 8 {
-            return MoveStatus::Invalid;
+        if !m_piece.from.in_bounds(self.size()) || !m_piece.to.in_bounds(self.size()) {
+            return Err(MoveError::OutOfBounds);
         }
-        if self.board[m_piece.i][m_piece.j] > 0 {
-            return match self.board[m_piece.i][m_piece.j] {
-                PAWN_BLACK | PAWN_WHITE => panic!("Pawns not supported!"),
-
-                BISHOP_BLACK | BISHOP_WHITE => self.validate_move_bishop(m_piece),
+        let piece = match self[m_piece.from] {
+            // enemies never move
+            Cell::Piece(Color::Black, _) => return Err(MoveError::NotYourPiece),
+            Cell::Piece(_, piece) => piece,
+            // empty squares, stars and walls have nothing to move
+            Cell::Empty | Cell::Star | Cell::Obstacle => return Err(MoveError::NoPiece),
+        };
+        // the player can't land on a wall, one of their own pieces or a guarded square
+        match self[m_piece.to] {
+            Cell::Obstacle => return Err(MoveError::Blocked(m_piece.to)),
+            Cell::Piece(Color::White, _) => return Err(MoveError::Occupied(m_piece.to)),
+            _ => (),
+        }
+        match piece {
+            Piece::Pawn => self.validate_move_pawn(m_piece),
 
-                KNIGHT_BLACK | KNIGHT_WHITE => self.validate_move_knight(m_piece),
+            Piece::Bishop => self.validate_move_bishop(m_piece),
 
-                ROOK_BLACK | ROOK_WHITE => self.validate_move_rook(m_piece),
+            Piece::Knight => self.validate_move_knight(m_piece),
 
-                KING_BLACK | KING_WHITE => panic!("Kings not supported!"),
+            Piece::Rook => self.validate_move_rook(m_piece),
 
-                QUEEN_BLACK | QUEEN_WHITE => self.validate_move_queen(m_piece),
+            Piece::King => self.validate_move_king(m_piece),
 
-                _ => panic!("Unexpected case!"),
-            };
+            Piece::Queen => self.validate_move_queen(m_piece),
+        }?;
+        match self.attacker(m_piece.to) {
+            Some(sq) => Err(MoveError::Attacked(sq)),
+            None => Ok(()),
         }
-        MoveStatus::Invalid
     }
     pub fn update_board(&mut self, m_piece: &MovePiece) {
-        let temp = self.board[m_piece.i][m_piece.j];
-        self.board[m_piece.goal_i][m_piece.goal_j] = temp;
-        self.board[m_piece.i][m_piece.j] = 0;
-    }
-}
-
-use std::cmp;
-use std::collections::HashMap;
-use std::collections::VecDeque;
-
-pub type SolutionPath = rpds::Vector<MovePiece>;
-impl LiBoard {
-    // calculates the number of moves to optimally collect all stars
-    // The idea is to perform a breadth first search till the desired move is found
-    // TODO: make bidirectional BFS
-    pub fn num_optimal_moves_to_star(&self) -> (i8, SolutionPath) {
-        // pair of (num stars collected , board)
-        let mut max_stacksize = 1;
-        let mut visited: HashMap<[[i8; 8]; 8], i8> = HashMap::new();
-        let mut current_queue = VecDeque::new();
-        current_queue.reserve(100_000);
-        let sol_path = rpds::Vector::new();
-
-        current_queue.push_back((0, 0, self.board, self.main_piece.0, self.main_piece.1, sol_path.clone()));
-        let mut min_num = i8::MAX;
-        while !current_queue.is_empty() {
-            max_stacksize = cmp::max(max_stacksize, current_queue.len());
-            // get current board
-            let mut cur_board = LiBoard {
-                main_piece: (0, 0), // doesn't matter here
-                num_star_cnt: 0,    // doesn't matter either
-                board: current_queue.front().unwrap().2,
-            };
-            // if board is not in visited
-            if let std::collections::hash_map::Entry::Vacant(e) = visited.entry(cur_board.board) {
-                // add to visited
-                let cur_starcount = current_queue.front().unwrap().0;
-                let cur_move_count = current_queue.front().unwrap().1;
-                let piece_ipos = current_queue.front().unwrap().3;
-                let piece_jpos = current_queue.front().unwrap().4;
-                let path = current_queue.front().unwrap().5.clone();
-                e.insert(cur_move_count);
-                if cur_starcount == self.num_star_cnt {
-                    min_num = cmp::min(cur_move_count, min_num);
-                    return (min_num, path);
-                }
-                current_queue.pop_front();
-                use itertools::iproduct;
-                for (k, l) in iproduct!(0..8, 0..8) {
-                    let temp_move = MovePiece {
-                        i: piece_ipos as usize,
-                        j: piece_jpos as usize,
-                        goal_i: k as usize,
-                        goal_j: l as usize,
-                    };
-                    if (temp_move.i != temp_move.goal_i || temp_move.j != temp_move.goal_j)
-                        && (cur_board.board[temp_move.i][temp_move.j] != 0 && cur_board.board[temp_move.i][temp_move.j] != STAR_VALUE && cur_board.validate_move(&temp_move).is_valid())
-                    {
-                        // add this to currentQueue
-                        let backup = LiBoard { ..cur_board };
-                        let star_flag = cur_board.board[temp_move.goal_i][temp_move.goal_j] == STAR_VALUE;
-
-                        cur_board.update_board(&temp_move);
-
-                        current_queue.push_back((
-                            cur_starcount + star_flag as i8,
-                            cur_move_count + 1,
-                            cur_board.board,
-                            temp_move.goal_i as i8,
-                            temp_move.goal_j as i8,
-                            path.push_back(temp_move.clone()).clone(),
-                        ));
-
-                        cur_board.board = backup.board;
-                    }
-                }
-            } else {
-                // Ignore if board has already been visited
-                current_queue.pop_front();
+        let mut temp = self[m_piece.from];
+        // promote pawns that reach the last rank
+        if let Cell::Piece(color, Piece::Pawn) = temp {
+            if (color == Color::White && m_piece.to.i == 0) || (color == Color::Black && m_piece.to.i == self.size() - 1) {
+                temp = Cell::Piece(color, self.promotion_piece);
             }
         }
-        (min_num, sol_path)
+        self[m_piece.to] = temp;
+        self[m_piece.from] = Cell::Empty;
+        for sq in self.pieces.iter_mut().filter(|sq| **sq == m_piece.from) {
+            *sq = m_piece.to;
+        }
     }
 }
 
+pub type SolutionPath = rpds::Vector<MovePiece>;
+
 impl Default for LiBoard {
     fn default() -> Self {
-        Self::new(5, QUEEN_WHITE)
+        Self::new(5, Piece::Queen)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    // shorthands for board literals
+    const E: Cell = Cell::Empty;
+    const S: Cell = Cell::Star;
+    const X: Cell = Cell::Obstacle;
+    const WQ: Cell = Cell::Piece(Color::White, Piece::Queen);
+    const WN: Cell = Cell::Piece(Color::White, Piece::Knight);
+    const WR: Cell = Cell::Piece(Color::White, Piece::Rook);
+    const WB: Cell = Cell::Piece(Color::White, Piece::Bishop);
+    const WK: Cell = Cell::Piece(Color::White, Piece::King);
+    const WP: Cell = Cell::Piece(Color::White, Piece::Pawn);
+    const BN: Cell = Cell::Piece(Color::Black, Piece::Knight);
+    const BR: Cell = Cell::Piece(Color::Black, Piece::Rook);
+    const BB: Cell = Cell::Piece(Color::Black, Piece::Bishop);
+
     #[test]
     fn test_optimal_calc() {
         let board = LiBoard {
-            board: [
-                [QUEEN_WHITE, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, STAR_VALUE],
+            board: vec![
+                vec![WQ, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, S],
             ],
             num_star_cnt: 1,
-            main_piece: (0, 0),
+            pieces: vec![Square::new(0, 0)],
+            promotion_piece: Piece::Queen,
         };
-        assert_eq!(1, board.num_optimal_moves_to_star().0)
+        assert_eq!(1, board.num_optimal_moves_to_star().unwrap().0)
     }
 
     #[test]
     fn test_optimal_calc_2() {
         let board = LiBoard {
-            board: [
-                [QUEEN_WHITE, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, STAR_VALUE, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, STAR_VALUE],
+            board: vec![
+                vec![WQ, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, S, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, S],
             ],
             num_star_cnt: 2,
-            main_piece: (0, 0),
+            pieces: vec![Square::new(0, 0)],
+            promotion_piece: Piece::Queen,
         };
-        assert_eq!(3, board.num_optimal_moves_to_star().0)
+        assert_eq!(3, board.num_optimal_moves_to_star().unwrap().0)
     }
 
     #[test]
     fn test_optimal_calc_3() {
         let board = LiBoard {
-            board: [
-                [QUEEN_WHITE, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, STAR_VALUE, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, STAR_VALUE, 0, 0, 0, STAR_VALUE, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, STAR_VALUE],
+            board: vec![
+                vec![WQ, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, S, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, S, E, E, E, S, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, S],
             ],
             num_star_cnt: 4,
-            main_piece: (0, 0),
+            pieces: vec![Square::new(0, 0)],
+            promotion_piece: Piece::Queen,
         };
-        assert_eq!(5, board.num_optimal_moves_to_star().0)
+        assert_eq!(5, board.num_optimal_moves_to_star().unwrap().0)
     }
 
     #[test]
     fn test_optimal_calc_4() {
         let board = LiBoard {
-            board: [
-                [KNIGHT_WHITE, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, STAR_VALUE, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [STAR_VALUE, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, STAR_VALUE, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, STAR_VALUE],
+            board: vec![
+                vec![WN, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, S, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![S, E, E, E, E, E, E, E],
+                vec![E, E, S, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, S],
             ],
             num_star_cnt: 4,
-            main_piece: (0, 0),
+            pieces: vec![Square::new(0, 0)],
+            promotion_piece: Piece::Queen,
         };
-        assert_eq!(10, board.num_optimal_moves_to_star().0)
+        assert_eq!(10, board.num_optimal_moves_to_star().unwrap().0)
     }
 
     #[test]
     fn test_optimal_calc_5() {
         let board = LiBoard {
-            board: [
-                [QUEEN_WHITE, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, STAR_VALUE],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, STAR_VALUE, 0, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [0, 0, STAR_VALUE, STAR_VALUE, 0, 0, 0, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0],
-                [STAR_VALUE, 0, 0, 0, 0, 0, STAR_VALUE, 0],
+            board: vec![
+                vec![WQ, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, S],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, S, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, S, S, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![S, E, E, E, E, E, S, E],
             ],
             num_star_cnt: 6,
-            main_piece: (0, 0),
+            pieces: vec![Square::new(0, 0)],
+            promotion_piece: Piece::Queen,
+        };
+        assert_eq!(6, board.num_optimal_moves_to_star().unwrap().0)
+    }
+
+    #[test]
+    fn test_optimal_calc_king() {
+        let board = LiBoard {
+            board: vec![
+                vec![WK, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, S, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, S],
+            ],
+            num_star_cnt: 2,
+            pieces: vec![Square::new(0, 0)],
+            promotion_piece: Piece::Queen,
+        };
+        assert_eq!(7, board.num_optimal_moves_to_star().unwrap().0)
+    }
+
+    #[test]
+    fn test_king_moves_one_square() {
+        let board = LiBoard::from_seed(1, 0, Piece::King);
+        let Square { i, j } = board.pieces[0];
+        for (k, l) in itertools::iproduct!(0..8, 0..8) {
+            let m = MovePiece {
+                from: board.pieces[0],
+                to: Square::new(k, l),
+            };
+            let expected = (k, l) != (i, j) && (k as i8 - i as i8).abs() <= 1 && (l as i8 - j as i8).abs() <= 1;
+            assert_eq!(expected, board.validate_move(&m).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_pawn_moves() {
+        let mut board = LiBoard {
+            board: vec![vec![E; 8]; 8],
+            num_star_cnt: 1,
+            pieces: vec![Square::new(6, 3)],
+            promotion_piece: Piece::Queen,
+        };
+        board[Square::new(6, 3)] = WP;
+        board[Square::new(5, 4)] = S;
+        let m = |goal_i, goal_j| MovePiece {
+            from: Square::new(6, 3),
+            to: Square::new(goal_i, goal_j),
+        };
+        assert!(board.validate_move(&m(5, 3)).is_ok());
+        assert!(board.validate_move(&m(4, 3)).is_ok());
+        assert!(board.validate_move(&m(5, 4)).is_ok());
+        // diagonals need a star, pawns never go backwards or triple push
+        assert!(board.validate_move(&m(5, 2)).is_err());
+        assert!(board.validate_move(&m(7, 3)).is_err());
+        assert!(board.validate_move(&m(3, 3)).is_err());
+    }
+
+    #[test]
+    fn test_optimal_calc_pawn_promotion() {
+        let board = LiBoard {
+            board: vec![
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![WP, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, S],
+            ],
+            num_star_cnt: 1,
+            pieces: vec![Square::new(6, 0)],
+            promotion_piece: Piece::Queen,
+        };
+        // 6, 4, 3, 2, 1, 0 (promote) then the queen goes straight to the star
+        assert_eq!(6, board.num_optimal_moves_to_star().unwrap().0);
+
+        let knight_promotion = LiBoard {
+            promotion_piece: Piece::Knight,
+            ..board
+        };
+        assert!(knight_promotion.num_optimal_moves_to_star().is_some());
+    }
+
+    #[test]
+    fn test_bishop_boards_are_solvable() {
+        for seed in 0..20 {
+            let board = LiBoard::from_seed(seed, 4, Piece::Bishop);
+            let Square { i, j } = board.pieces[0];
+            for (k, l) in itertools::iproduct!(0..8, 0..8) {
+                if board.board[k][l] == S {
+                    assert_eq!((i + j) % 2, (k + l) % 2);
+                }
+            }
+            assert!(board.num_optimal_moves_to_star().is_some());
+        }
+    }
+
+    #[test]
+    fn test_unsolvable_board() {
+        let mut board = LiBoard {
+            board: vec![vec![E; 8]; 8],
+            num_star_cnt: 1,
+            pieces: vec![Square::new(0, 0)],
+            promotion_piece: Piece::Queen,
         };
-        assert_eq!(6, board.num_optimal_moves_to_star().0)
+        board[Square::new(0, 0)] = WB;
+        board[Square::new(0, 1)] = S;
+        assert!(board.num_optimal_moves_to_star().is_none());
+        assert!(!board.is_greedy_solvable());
+    }
+
+    #[test]
+    fn test_pawn_bishop_promotion_boards_are_solvable() {
+        for seed in 0..20 {
+            let board = LiBoard::from_options_seeded(
+                &BoardOptions {
+                    star_cnt: 3,
+                    choice_piece: Piece::Pawn,
+                    promotion_piece: Piece::Bishop,
+                    ..Default::default()
+                },
+                seed,
+            );
+            assert!(board.num_optimal_moves_to_star().is_some());
+        }
+    }
+    #[test]
+    fn test_enemy_attacks() {
+        let mut board = LiBoard {
+            board: vec![vec![E; 8]; 8],
+            num_star_cnt: 0,
+            pieces: vec![Square::new(0, 0)],
+            promotion_piece: Piece::Queen,
+        };
+        board[Square::new(0, 0)] = WR;
+        board[Square::new(2, 6)] = BN;
+        board[Square::new(4, 6)] = BR;
+        board[Square::new(6, 1)] = BB;
+        let m = |goal_i, goal_j| MovePiece {
+            from: Square::new(0, 0),
+            to: Square::new(goal_i, goal_j),
+        };
+        // guarded by the knight and the bishop
+        assert!(board.validate_move(&m(0, 7)).is_err());
+        assert!(board.validate_move(&m(0, 6)).is_ok());
+        // the knight is defended by the rook, the rook isn't defended
+        assert!(board.is_attacked(Square::new(2, 6)));
+        assert!(!board.is_attacked(Square::new(4, 6)));
+        // the knight blocks the rook from guarding squares behind it
+        assert!(!board.is_attacked(Square::new(0, 6)));
+        // enemies can't be moved
+        assert_eq!(
+            Err(MoveError::NotYourPiece),
+            board.validate_move(&MovePiece {
+                from: Square::new(2, 6),
+                to: Square::new(0, 5),
+            })
+        );
+    }
+
+    #[test]
+    fn test_move_errors() {
+        let mut board = LiBoard {
+            board: vec![vec![E; 8]; 8],
+            num_star_cnt: 1,
+            pieces: vec![Square::new(7, 0)],
+            promotion_piece: Piece::Queen,
+        };
+        board[Square::new(7, 0)] = WR;
+        board[Square::new(4, 0)] = S;
+        board[Square::new(5, 5)] = BB;
+        let m = |i, j, goal_i, goal_j| MovePiece {
+            from: Square::new(i, j),
+            to: Square::new(goal_i, goal_j),
+        };
+        assert_eq!(Err(MoveError::SameSquare), board.validate_move(&m(7, 0, 7, 0)));
+        assert_eq!(Err(MoveError::OutOfBounds), board.validate_move(&m(7, 0, 8, 0)));
+        assert_eq!(Err(MoveError::NoPiece), board.validate_move(&m(6, 0, 5, 0)));
+        assert_eq!(Err(MoveError::IllegalGeometry(Piece::Rook)), board.validate_move(&m(7, 0, 6, 1)));
+        // the star can be collected but not passed
+        assert_eq!(Ok(()), board.validate_move(&m(7, 0, 4, 0)));
+        assert_eq!(Err(MoveError::Blocked(Square::new(4, 0))), board.validate_move(&m(7, 0, 3, 0)));
+        assert_eq!(Err(MoveError::Attacked(Square::new(5, 5))), board.validate_move(&m(7, 0, 7, 3)));
+        assert_eq!("That square is guarded by the piece on f3", MoveError::Attacked(Square::new(5, 5)).message(8));
+        assert_eq!("The way is blocked on a5", MoveError::Blocked(Square::new(0, 0)).message(5));
+        // leapers can't land on the player's other pieces either
+        let board = LiBoard::from_notation("8 Nb1,Rc3,Kb2 h8 - -").unwrap();
+        assert_eq!(Err(MoveError::Occupied(Square::new(5, 2))), board.validate_move(&m(7, 1, 5, 2)));
+        assert_eq!(Err(MoveError::Occupied(Square::new(5, 2))), board.validate_move(&m(6, 1, 5, 2)));
+        assert_eq!("Your own piece is on c3", MoveError::Occupied(Square::new(5, 2)).message(8));
+    }
+
+    #[test]
+    fn test_legal_moves_match_validate_move() {
+        for size in BOARD_SIZES {
+            for piece in [Piece::Queen, Piece::Knight, Piece::Rook, Piece::Bishop, Piece::King, Piece::Pawn] {
+                for n in 0..10 {
+                    let limits = CountLimits::for_size(size);
+                    let board = LiBoard::from_options_seeded(
+                        &BoardOptions {
+                            size,
+                            star_cnt: 6.min(limits.stars),
+                            choice_piece: piece,
+                            // every other board has more pieces to land on
+                            extra_pieces: if n % 2 == 0 { vec![] } else { vec![Piece::Knight, Piece::King] },
+                            enemy_cnt: 3.min(limits.enemies),
+                            obstacle_cnt: 5.min(limits.obstacles),
+                            ..Default::default()
+                        },
+                        n,
+                    );
+                    for &from in &board.pieces {
+                        let mut generated: Vec<Square> = board.legal_moves(from).map(|m| m.to).collect();
+                        generated.sort_by_key(|sq| (sq.i, sq.j));
+                        let validated: Vec<Square> = board.squares().filter(|&to| board.validate_move(&MovePiece { from, to }).is_ok()).collect();
+                        assert_eq!(validated, generated, "{:?}", board);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_optimal_calc_enemy_capture() {
+        let board = LiBoard {
+            board: vec![
+                vec![WR, E, E, E, E, E, E, S],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, BN, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+            ],
+            num_star_cnt: 1,
+            pieces: vec![Square::new(0, 0)],
+            promotion_piece: Piece::Queen,
+        };
+        // the star is guarded, so the knight has to be taken first
+        assert_eq!(4, board.num_optimal_moves_to_star().unwrap().0);
+    }
+
+    #[test]
+    fn test_enemy_boards_are_solvable() {
+        for seed in 0..10 {
+            let board = LiBoard::from_options_seeded(
+                &BoardOptions {
+                    star_cnt: 3,
+                    choice_piece: Piece::Rook,
+                    enemy_cnt: 3,
+                    ..Default::default()
+                },
+                seed,
+            );
+            assert!(board.num_optimal_moves_to_star().is_some());
+        }
+    }
+
+    #[test]
+    fn test_optimal_calc_rook_around_wall() {
+        let board = LiBoard {
+            board: vec![
+                vec![WR, E, E, X, E, E, E, S],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+            ],
+            num_star_cnt: 1,
+            pieces: vec![Square::new(0, 0)],
+            promotion_piece: Piece::Queen,
+        };
+        assert_eq!(
+            Err(MoveError::Blocked(Square::new(0, 3))),
+            board.validate_move(&MovePiece {
+                from: Square::new(0, 0),
+                to: Square::new(0, 3),
+            })
+        );
+        assert_eq!(3, board.num_optimal_moves_to_star().unwrap().0)
+    }
+
+    #[test]
+    fn test_optimal_calc_queen_around_wall() {
+        let board = LiBoard {
+            board: vec![
+                vec![WQ, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, X, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, S],
+            ],
+            num_star_cnt: 1,
+            pieces: vec![Square::new(0, 0)],
+            promotion_piece: Piece::Queen,
+        };
+        assert_eq!(2, board.num_optimal_moves_to_star().unwrap().0)
+    }
+
+    #[test]
+    fn test_optimal_calc_bishop_around_wall() {
+        let board = LiBoard {
+            board: vec![
+                vec![WB, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, X, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, E],
+                vec![E, E, E, E, E, E, E, S],
+            ],
+            num_star_cnt: 1,
+            pieces: vec![Square::new(0, 0)],
+            promotion_piece: Piece::Queen,
+        };
+        assert_eq!(5, board.num_optimal_moves_to_star().unwrap().0)
+    }
+
+    #[test]
+    fn test_wall_blocks_enemy_attack() {
+        let mut board = LiBoard {
+            board: vec![vec![E; 8]; 8],
+            num_star_cnt: 0,
+            pieces: vec![Square::new(7, 7)],
+            promotion_piece: Piece::Queen,
+        };
+        board[Square::new(7, 7)] = WN;
+        board[Square::new(0, 0)] = BR;
+        board[Square::new(0, 4)] = X;
+        assert!(board.is_attacked(Square::new(0, 3)));
+        assert!(!board.is_attacked(Square::new(0, 5)));
+        // knights jump over walls but can't land on them
+        board[Square::new(6, 6)] = X;
+        board[Square::new(5, 6)] = X;
+        assert_eq!(
+            Err(MoveError::Blocked(Square::new(5, 6))),
+            board.validate_move(&MovePiece {
+                from: Square::new(7, 7),
+                to: Square::new(5, 6),
+            })
+        );
+        assert!(board
+            .validate_move(&MovePiece {
+                from: Square::new(7, 7),
+                to: Square::new(6, 5),
+            })
+            .is_ok());
+    }
+
+    #[test]
+    fn test_optimal_calc_two_pieces() {
+        // each piece takes the star next to it
+        let mut board = LiBoard {
+            board: vec![vec![E; 8]; 8],
+            num_star_cnt: 2,
+            pieces: vec![Square::new(0, 0), Square::new(7, 7)],
+            promotion_piece: Piece::Queen,
+        };
+        board[Square::new(0, 0)] = WR;
+        board[Square::new(7, 7)] = WN;
+        board[Square::new(0, 5)] = S;
+        board[Square::new(5, 6)] = S;
+        assert_eq!(2, board.num_optimal_moves_to_star().unwrap().0);
+        // the rook can't slide through the knight
+        let mut board = LiBoard {
+            board: vec![vec![E; 8]; 8],
+            num_star_cnt: 1,
+            pieces: vec![Square::new(7, 0), Square::new(7, 3)],
+            promotion_piece: Piece::Queen,
+        };
+        board[Square::new(7, 0)] = WR;
+        board[Square::new(7, 3)] = WN;
+        board[Square::new(7, 5)] = S;
+        assert_eq!(2, board.num_optimal_moves_to_star().unwrap().0);
+    }
+
+    #[test]
+    fn test_seeded_boards_repeat() {
+        assert_eq!(LiBoard::from_seed(42, 6, Piece::Knight).board, LiBoard::from_seed(42, 6, Piece::Knight).board);
+        assert_ne!(LiBoard::from_seed(42, 6, Piece::Knight).board, LiBoard::from_seed(43, 6, Piece::Knight).board);
+        // rerolled boards come from the same numbers too
+        let options = BoardOptions {
+            size: 6,
+            star_cnt: 8,
+            choice_piece: Piece::Pawn,
+            extra_pieces: vec![Piece::Rook],
+            enemy_cnt: 2,
+            obstacle_cnt: 3,
+            ..Default::default()
+        };
+        for seed in 0..5 {
+            assert_eq!(LiBoard::from_options_seeded(&options, seed).board, LiBoard::from_options_seeded(&options, seed).board);
+        }
+    }
+
+    #[test]
+    fn test_difficulty_targets() {
+        for difficulty in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard] {
+            for piece in [Piece::Queen, Piece::Knight] {
+                let target = difficulty.target();
+                let board = LiBoard::from_options_seeded(
+                    &BoardOptions {
+                        star_cnt: difficulty.star_cnt(),
+                        choice_piece: piece,
+                        enemy_cnt: 2,
+                        obstacle_cnt: 3,
+                        target: Some(target.clone()),
+                        ..Default::default()
+                    },
+                    7,
+                );
+                let (move_cnt, route_cnt) = board.optimal_route_count().unwrap();
+                assert!(target.is_met(move_cnt, route_cnt), "{:?} took {} moves", difficulty, move_cnt);
+            }
+        }
+        // a Hard board needs few routes as well as enough moves
+        assert!(!Difficulty::Hard.target().is_met(12, HARD_MAX_ROUTES + 1));
+        assert!(!Difficulty::Hard.target().is_met(10, 1));
     }
 }
@@ -3,7 +3,14 @@
 #![allow(clippy::unnecessary_unwrap)]
 
 mod app;
+mod bitboard;
 mod chess;
+mod movegen;
+mod notation;
+mod pack;
+mod replay;
+mod solver;
+mod stats;
 use eframe::emath::Vec2;
 mod egui_widgets;
 
@@ -19,7 +26,7 @@ fn main() {
         ..Default::default()
     };
 
-    eframe::run_native("LiLearn", options, Box::new(|_cc| Box::new(app::MyApp::default())));
+    eframe::run_native("LiLearn", options, Box::new(|cc| Box::new(app::MyApp::new(cc))));
 }
 
 // ----------------------------------------------------------------------------
@@ -28,5 +35,5 @@ fn main() {
 pub fn main() {
     let web_options = eframe::WebOptions::default();
     use lib::MyApp;
-    eframe::start_web("lilearn_id", web_options, Box::new(|cc| Box::new(MyApp::default())));
+    eframe::start_web("lilearn_id", web_options, Box::new(|cc| Box::new(MyApp::new(cc))));
 }
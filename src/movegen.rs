@@ -0,0 +1,32 @@
+use crate::bitboard::BitBoard;
+use crate::chess::*;
+
+// ray directions, rook directions first then bishop directions. Kings step one square along each.
+pub const DIRECTIONS: [(i8, i8); 8] = [(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (-1, 1), (1, -1), (1, 1)];
+pub const KNIGHT_OFFSETS: [(i8, i8); 8] = [(-2, -1), (-2, 1), (-1, -2), (-1, 2), (1, -2), (1, 2), (2, -1), (2, 1)];
+
+// the square one step away on a board of the size, if it's on the board
+pub fn offset(size: usize, sq: Square, (di, dj): (i8, i8)) -> Option<Square> {
+    let to = Square::new((sq.i as isize + di as isize) as usize, (sq.j as isize + dj as isize) as usize);
+    // negative coordinates wrap around to huge ones, which are out of bounds too
+    to.in_bounds(size).then_some(to)
+}
+
+impl LiBoard {
+    // Every move the player's piece on from can make right now.
+    // The moves come from the same bitboard tables the solver searches with, so the two can't disagree.
+    pub fn legal_moves(&self, from: Square) -> impl Iterator<Item = MovePiece> {
+        let bitboard = BitBoard::from(self);
+        let t = bitboard.tables;
+        let targets = match self[from] {
+            Cell::Piece(color, piece) => {
+                let other = if color == Color::White { Color::Black } else { Color::White };
+                // the piece's own side can't be landed on, so they're walls to it
+                let walls = bitboard.obstacles | bitboard.color_mask(color);
+                t.moves(t.index(from), color, piece, bitboard.stars, bitboard.color_mask(other), walls) & !bitboard.attacked()
+            }
+            _ => 0,
+        };
+        t.squares(targets).map(move |to| MovePiece { from, to })
+    }
+}
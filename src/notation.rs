@@ -0,0 +1,258 @@
+use crate::chess::*;
+use std::fmt;
+
+// One line puzzle notation, fields split by spaces:
+//   size, the player's pieces, stars, obstacles, enemies, promotion piece
+// Pieces are a letter and a square, lists are split by commas and empty lists are a dash.
+//   8 Qd4,Nb1 a1,c3,h8 e5 Rf3,Pb2 Q
+
+// Why a puzzle couldn't be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotationError {
+    // the field with this name is missing
+    MissingField(&'static str),
+    // more than the six fields
+    ExtraField(String),
+    BadSize(String),
+    BadPiece(String),
+    BadSquare(String),
+    // two things on the same square
+    Overlap(String),
+    // the player needs between one and MAX_PLAYER_PIECES pieces
+    PieceCount(usize),
+    // the player's pawns can't start where they would already have promoted, or behind their starting rank
+    PawnOnBackRank(String),
+    BadPromotion(String),
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotationError::MissingField(field) => write!(f, "The {} are missing", field),
+            NotationError::ExtraField(field) => write!(f, "Didn't expect \"{}\" at the end", field),
+            NotationError::BadSize(size) => write!(f, "\"{}\" isn't one of the board sizes {:?}", size, BOARD_SIZES),
+            NotationError::BadPiece(piece) => write!(f, "\"{}\" isn't a piece letter and a square", piece),
+            NotationError::BadSquare(sq) => write!(f, "\"{}\" isn't a square on the board", sq),
+            NotationError::Overlap(sq) => write!(f, "{} has more than one thing on it", sq),
+            NotationError::PieceCount(cnt) => write!(f, "Puzzles need 1 to {} pieces, not {}", MAX_PLAYER_PIECES, cnt),
+            NotationError::PawnOnBackRank(sq) => write!(f, "The pawn on {} can't start on the first or last rank", sq),
+            NotationError::BadPromotion(piece) => write!(f, "Pawns can't promote to \"{}\"", piece),
+        }
+    }
+}
+
+const fn piece_letter(piece: Piece) -> char {
+    match piece {
+        Piece::Pawn => 'P',
+        Piece::Knight => 'N',
+        Piece::Bishop => 'B',
+        Piece::Rook => 'R',
+        Piece::Queen => 'Q',
+        Piece::King => 'K',
+    }
+}
+
+fn parse_piece_letter(letter: char) -> Option<Piece> {
+    match letter {
+        'P' => Some(Piece::Pawn),
+        'N' => Some(Piece::Knight),
+        'B' => Some(Piece::Bishop),
+        'R' => Some(Piece::Rook),
+        'Q' => Some(Piece::Queen),
+        'K' => Some(Piece::King),
+        _ => None,
+    }
+}
+
+// a file letter then a rank number, like Square::name gives
+fn parse_square(name: &str, size: usize) -> Result<Square, NotationError> {
+    let bad = || NotationError::BadSquare(name.to_owned());
+    let mut chars = name.chars();
+    let file = chars.next().filter(char::is_ascii_lowercase).ok_or_else(bad)?;
+    // parse takes a leading + as well, which isn't part of a square name
+    let rank = chars.as_str();
+    if !rank.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(bad());
+    }
+    let rank: usize = rank.parse().map_err(|_| bad())?;
+    let j = (file as u8 - b'a') as usize;
+    if j >= size || rank == 0 || rank > size {
+        return Err(bad());
+    }
+    Ok(Square::new(size - rank, j))
+}
+
+// a piece letter and the rest, which should be a square
+fn parse_piece(item: &str) -> Result<(Piece, &str), NotationError> {
+    let mut chars = item.chars();
+    let piece = chars.next().and_then(parse_piece_letter).ok_or_else(|| NotationError::BadPiece(item.to_owned()))?;
+    Ok((piece, chars.as_str()))
+}
+
+// puts the cell on the named square, which has to still be empty
+fn place(board: &mut LiBoard, name: &str, cell: Cell) -> Result<Square, NotationError> {
+    let sq = parse_square(name, board.size())?;
+    if board[sq] != Cell::Empty {
+        return Err(NotationError::Overlap(name.to_owned()));
+    }
+    board[sq] = cell;
+    Ok(sq)
+}
+
+// comma separated items, or nothing for a dash
+fn split_list(field: &str) -> impl Iterator<Item = &str> {
+    field.split(',').filter(move |_| field != "-")
+}
+
+fn format_list(items: Vec<String>) -> String {
+    if items.is_empty() {
+        "-".to_owned()
+    } else {
+        items.join(",")
+    }
+}
+
+impl LiBoard {
+    // the board as it is now in one line notation
+    pub fn to_notation(&self) -> String {
+        let size = self.size();
+        let mut pieces = Vec::new();
+        let mut stars = Vec::new();
+        let mut obstacles = Vec::new();
+        let mut enemies = Vec::new();
+        for sq in self.squares() {
+            match self[sq] {
+                Cell::Empty => (),
+                Cell::Star => stars.push(sq.name(size)),
+                Cell::Obstacle => obstacles.push(sq.name(size)),
+                Cell::Piece(Color::White, _) => (),
+                Cell::Piece(Color::Black, piece) => enemies.push(format!("{}{}", piece_letter(piece), sq.name(size))),
+            }
+        }
+        // in the order the pieces were placed, so the first one is still the main piece
+        for &sq in &self.pieces {
+            if let Cell::Piece(Color::White, piece) = self[sq] {
+                pieces.push(format!("{}{}", piece_letter(piece), sq.name(size)));
+            }
+        }
+        format!(
+            "{} {} {} {} {} {}",
+            size,
+            format_list(pieces),
+            format_list(stars),
+            format_list(obstacles),
+            format_list(enemies),
+            piece_letter(self.promotion_piece)
+        )
+    }
+
+    // Reads a board written by to_notation. Extra spaces are fine and the promotion piece can be left off.
+    pub fn from_notation(notation: &str) -> Result<LiBoard, NotationError> {
+        let mut fields = notation.split_whitespace();
+        let mut next_field = |name| fields.next().ok_or(NotationError::MissingField(name));
+        let size_field = next_field("board size")?;
+        let size = size_field
+            .parse::<usize>()
+            .ok()
+            .filter(|size| BOARD_SIZES.contains(size))
+            .ok_or_else(|| NotationError::BadSize(size_field.to_owned()))?;
+        let mut board = LiBoard {
+            board: vec![vec![Cell::Empty; size]; size],
+            pieces: Vec::new(),
+            num_star_cnt: 0,
+            promotion_piece: Piece::Queen,
+        };
+        for item in split_list(next_field("pieces")?) {
+            let (piece, name) = parse_piece(item)?;
+            let sq = place(&mut board, name, Cell::Piece(Color::White, piece))?;
+            if piece == Piece::Pawn && (sq.i == 0 || sq.i == size - 1) {
+                return Err(NotationError::PawnOnBackRank(name.to_owned()));
+            }
+            board.pieces.push(sq);
+        }
+        if !(1..=MAX_PLAYER_PIECES).contains(&board.pieces.len()) {
+            return Err(NotationError::PieceCount(board.pieces.len()));
+        }
+        for name in split_list(next_field("stars")?) {
+            place(&mut board, name, Cell::Star)?;
+            board.num_star_cnt += 1;
+        }
+        for name in split_list(next_field("obstacles")?) {
+            place(&mut board, name, Cell::Obstacle)?;
+        }
+        for item in split_list(next_field("enemies")?) {
+            let (piece, name) = parse_piece(item)?;
+            place(&mut board, name, Cell::Piece(Color::Black, piece))?;
+        }
+        if let Some(field) = fields.next() {
+            board.promotion_piece = match field.chars().next().and_then(parse_piece_letter) {
+                Some(piece @ (Piece::Knight | Piece::Bishop | Piece::Rook | Piece::Queen)) if field.len() == 1 => piece,
+                _ => return Err(NotationError::BadPromotion(field.to_owned())),
+            };
+        }
+        if let Some(field) = fields.next() {
+            return Err(NotationError::ExtraField(field.to_owned()));
+        }
+        Ok(board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notation_round_trip() {
+        for size in BOARD_SIZES {
+            let limits = CountLimits::for_size(size);
+            let board = LiBoard::from_options_seeded(
+                &BoardOptions {
+                    size,
+                    star_cnt: 5,
+                    choice_piece: Piece::Pawn,
+                    extra_pieces: vec![Piece::Knight],
+                    promotion_piece: Piece::Rook,
+                    enemy_cnt: 2.min(limits.enemies),
+                    obstacle_cnt: 3.min(limits.obstacles),
+                    ..Default::default()
+                },
+                1,
+            );
+            let notation = board.to_notation();
+            let back = LiBoard::from_notation(&notation).unwrap();
+            assert_eq!(board.board, back.board, "{}", notation);
+            assert_eq!(board.pieces, back.pieces);
+            assert_eq!(board.num_star_cnt, back.num_star_cnt);
+            assert_eq!(board.promotion_piece, back.promotion_piece);
+            assert_eq!(notation, back.to_notation());
+        }
+        let board = LiBoard::from_notation("8  Qd4,Nb1 a1,c3,h8 e5 Rf3,Pb2").unwrap();
+        assert_eq!(Cell::Piece(Color::White, Piece::Queen), board[Square::new(4, 3)]);
+        assert_eq!(Cell::Piece(Color::Black, Piece::Pawn), board[Square::new(6, 1)]);
+        assert_eq!(Cell::Star, board[Square::new(0, 7)]);
+        assert_eq!(Cell::Obstacle, board[Square::new(3, 4)]);
+        assert_eq!((3, Piece::Queen), (board.num_star_cnt, board.promotion_piece));
+    }
+
+    #[test]
+    fn test_notation_errors() {
+        let cases = [
+            ("", NotationError::MissingField("board size")),
+            ("8 Qd4 a1 -", NotationError::MissingField("enemies")),
+            ("12 Qd4 a1 - -", NotationError::BadSize("12".to_owned())),
+            ("7 Qd4 a1 - -", NotationError::BadSize("7".to_owned())),
+            ("8 Xd4 a1 - -", NotationError::BadPiece("Xd4".to_owned())),
+            ("8 Qd9 a1 - -", NotationError::BadSquare("d9".to_owned())),
+            ("8 Qd4 a+1 - -", NotationError::BadSquare("a+1".to_owned())),
+            ("8 Qd4 d4 - -", NotationError::Overlap("d4".to_owned())),
+            ("8 - a1 - -", NotationError::PieceCount(0)),
+            ("8 Pd8 a1 - -", NotationError::PawnOnBackRank("d8".to_owned())),
+            ("8 Pd1 a1 - -", NotationError::PawnOnBackRank("d1".to_owned())),
+            ("8 Qd4 a1 - - K", NotationError::BadPromotion("K".to_owned())),
+            ("8 Qd4 a1 - - Q x", NotationError::ExtraField("x".to_owned())),
+        ];
+        for (notation, err) in cases {
+            assert_eq!(Some(err), LiBoard::from_notation(notation).err(), "{}", notation);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use crate::chess::LiBoard;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Packs that ship with the game. The web build can't read files, so these are compiled in.
+static BUNDLED_PACKS: [&str; 2] = [include_str!("../packs/first_steps.json"), include_str!("../packs/guards_and_teams.json")];
+
+// An ordered set of puzzles to play through, stored as JSON
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PuzzlePack {
+    pub title: String,
+    pub puzzles: Vec<PackPuzzle>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PackPuzzle {
+    // written in puzzle notation so packs can be put together by hand
+    #[serde(with = "board_notation")]
+    pub board: LiBoard,
+    // how many moves the pack author counts as solving it, the optimal count if there's none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_moves: Option<i8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+// Why a pack couldn't be loaded
+#[derive(Debug)]
+pub enum PackError {
+    Read(std::io::Error),
+    // bad JSON, or a board that isn't valid notation
+    Format(serde_json::Error),
+    NoPuzzles,
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PackError::Read(err) => write!(f, "Couldn't read the pack: {}", err),
+            PackError::Format(err) => write!(f, "The pack isn't in the right format: {}", err),
+            PackError::NoPuzzles => write!(f, "The pack has no puzzles"),
+        }
+    }
+}
+
+mod board_notation {
+    use crate::chess::LiBoard;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(board: &LiBoard, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&board.to_notation())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<LiBoard, D::Error> {
+        let notation = String::deserialize(deserializer)?;
+        LiBoard::from_notation(&notation).map_err(|err| D::Error::custom(format!("{} in \"{}\"", err, notation)))
+    }
+}
+
+impl PuzzlePack {
+    pub fn from_json(json: &str) -> Result<PuzzlePack, PackError> {
+        let pack: PuzzlePack = serde_json::from_str(json).map_err(PackError::Format)?;
+        if pack.puzzles.is_empty() {
+            return Err(PackError::NoPuzzles);
+        }
+        Ok(pack)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &std::path::Path) -> Result<PuzzlePack, PackError> {
+        Self::from_json(&std::fs::read_to_string(path).map_err(PackError::Read)?)
+    }
+
+    pub fn bundled() -> Vec<PuzzlePack> {
+        BUNDLED_PACKS.iter().map(|json| Self::from_json(json).expect("bundled packs are valid")).collect()
+    }
+}
+
+// How far the player has got through a pack
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackProgress {
+    // fewest moves each puzzle was cleared in, None until it is
+    pub best: Vec<Option<i8>>,
+    // whether each puzzle was cleared in its target count
+    #[serde(default)]
+    pub solved: Vec<bool>,
+}
+
+impl PackProgress {
+    pub fn new(puzzle_cnt: usize) -> PackProgress {
+        PackProgress {
+            best: vec![None; puzzle_cnt],
+            solved: vec![false; puzzle_cnt],
+        }
+    }
+
+    pub fn solved_cnt(&self) -> usize {
+        self.solved.iter().filter(|&&solved| solved).count()
+    }
+
+    pub fn is_solved(&self, idx: usize) -> bool {
+        self.solved.get(idx).copied().unwrap_or(false)
+    }
+
+    // Keeps the best count, and marks the puzzle solved if it's within the target.
+    // Without a target any finish solves it.
+    pub fn record(&mut self, idx: usize, move_cnt: i8, target: Option<i8>) {
+        if idx >= self.best.len() {
+            self.best.resize(idx + 1, None);
+        }
+        if idx >= self.solved.len() {
+            self.solved.resize(idx + 1, false);
+        }
+        let best = &mut self.best[idx];
+        *best = Some(best.map_or(move_cnt, |b| b.min(move_cnt)));
+        if target.map_or(true, |target| move_cnt <= target) {
+            self.solved[idx] = true;
+        }
+    }
+
+    // The first unsolved puzzle after idx, going back round to the start.
+    // Just the next one once they're all solved.
+    pub fn next_puzzle(&self, idx: usize, puzzle_cnt: usize) -> usize {
+        (1..=puzzle_cnt)
+            .map(|step| (idx + step) % puzzle_cnt)
+            .find(|&next| !self.is_solved(next))
+            .unwrap_or((idx + 1) % puzzle_cnt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_packs() {
+        let packs = PuzzlePack::bundled();
+        assert!(!packs.is_empty());
+        for pack in &packs {
+            for (idx, puzzle) in pack.puzzles.iter().enumerate() {
+                // the targets are the optimal counts
+                let move_cnt = puzzle.board.optimal_route_count().map(|(move_cnt, _)| move_cnt);
+                assert_eq!(puzzle.target_moves, move_cnt, "{} puzzle {}", pack.title, idx + 1);
+            }
+            // written back out and read again, the boards are the same
+            let back = PuzzlePack::from_json(&serde_json::to_string(pack).unwrap()).unwrap();
+            assert_eq!(pack.title, back.title);
+            for (puzzle, puzzle_back) in pack.puzzles.iter().zip(&back.puzzles) {
+                assert_eq!(puzzle.board.to_notation(), puzzle_back.board.to_notation());
+                assert_eq!((puzzle.target_moves, &puzzle.hint), (puzzle_back.target_moves, &puzzle_back.hint));
+            }
+        }
+    }
+
+    #[test]
+    fn test_pack_errors() {
+        assert!(matches!(PuzzlePack::from_json(r#"{"title": "Empty", "puzzles": []}"#), Err(PackError::NoPuzzles)));
+        assert!(matches!(
+            PuzzlePack::from_json(r#"{"title": "Bad", "puzzles": [{"board": "8 Qd9 a1 - -"}]}"#),
+            Err(PackError::Format(_))
+        ));
+        assert!(matches!(PuzzlePack::from_json("not json"), Err(PackError::Format(_))));
+        // targets and hints can be left out
+        let pack = PuzzlePack::from_json(r#"{"title": "Plain", "puzzles": [{"board": "8 Qd4 a1 - -"}]}"#).unwrap();
+        assert_eq!((None, None), (pack.puzzles[0].target_moves, pack.puzzles[0].hint.clone()));
+    }
+
+    #[test]
+    fn test_pack_progress() {
+        let mut progress = PackProgress::new(4);
+        assert_eq!(1, progress.next_puzzle(0, 4));
+        // finishing over the target keeps the best count but doesn't solve the puzzle
+        progress.record(1, 5, Some(4));
+        progress.record(1, 7, Some(4));
+        assert_eq!((Some(5), 0), (progress.best[1], progress.solved_cnt()));
+        assert_eq!(1, progress.next_puzzle(0, 4));
+        progress.record(1, 4, Some(4));
+        assert_eq!(Some(4), progress.best[1]);
+        assert_eq!(1, progress.solved_cnt());
+        // solved puzzles are skipped, going back round to the start
+        assert_eq!(2, progress.next_puzzle(0, 4));
+        progress.record(3, 2, Some(2));
+        assert_eq!(0, progress.next_puzzle(2, 4));
+        // the first unsolved one when starting a pack
+        progress.record(0, 3, None);
+        assert_eq!(2, progress.next_puzzle(3, 4));
+        progress.record(2, 3, Some(3));
+        assert_eq!((4, 3), (progress.solved_cnt(), progress.next_puzzle(2, 4)));
+        // a puzzle past the end grows the list
+        progress.record(5, 1, Some(1));
+        assert!(progress.is_solved(5) && !progress.is_solved(4));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::chess::{Cell, LiBoard, MovePiece, SolutionPath, Square};
+
+// seconds a piece takes to slide to its square
+pub const SLIDE_SECS: f64 = 0.35;
+// seconds between moves while playing
+pub const STEP_SECS: f64 = 1.0;
+
+// Plays a solution route from the round's first board, one move at a time
+pub struct Replay {
+    start: LiBoard,
+    path: SolutionPath,
+    // the start board with the first `step` moves played
+    board: LiBoard,
+    // the board before the last move, shown under the sliding piece
+    before: LiBoard,
+    step: usize,
+    pub playing: bool,
+    // egui time the last move was played, None after jumping to a step
+    step_time: Option<f64>,
+}
+
+impl Replay {
+    pub fn new(start: &LiBoard, path: SolutionPath) -> Replay {
+        Replay {
+            start: start.clone(),
+            path,
+            board: start.clone(),
+            before: start.clone(),
+            step: 0,
+            playing: true,
+            step_time: None,
+        }
+    }
+
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    pub fn move_cnt(&self) -> usize {
+        self.path.len()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.step == self.path.len()
+    }
+
+    // the moves played so far, in order
+    pub fn played(&self) -> impl Iterator<Item = &MovePiece> {
+        self.path.iter().take(self.step)
+    }
+
+    // Plays the next move. Some(true) if it collected a star, None at the end of the route.
+    pub fn step_forward(&mut self, now: f64) -> Option<bool> {
+        let m = self.path.get(self.step)?.clone();
+        self.before = self.board.clone();
+        let star = self.board[m.to] == Cell::Star;
+        if star {
+            self.board.num_star_cnt -= 1;
+        }
+        self.board.update_board(&m);
+        self.step += 1;
+        self.step_time = Some(now);
+        Some(star)
+    }
+
+    // jump to the board after `step` moves without sliding
+    pub fn seek(&mut self, step: usize) {
+        self.board = self.start.clone();
+        self.step = 0;
+        for _ in 0..step.min(self.path.len()) {
+            self.step_forward(0.0);
+        }
+        self.before = self.board.clone();
+        self.step_time = None;
+    }
+
+    // plays the next move once it's due, see step_forward
+    pub fn tick(&mut self, now: f64) -> Option<bool> {
+        if !self.playing || self.step_time.map_or(false, |time| now - time < STEP_SECS) {
+            return None;
+        }
+        let played = self.step_forward(now);
+        if self.is_done() {
+            self.playing = false;
+        }
+        played
+    }
+
+    // the last move while its piece is still sliding, and how far along it is from 0 to 1
+    fn sliding(&self, now: f64) -> Option<(&MovePiece, f32)> {
+        let done = (now - self.step_time?) / SLIDE_SECS;
+        (done < 1.0).then(|| (&self.path[self.step - 1], done as f32))
+    }
+
+    // what to draw on the square. While a piece slides its old board shows, minus the piece.
+    pub fn cell(&self, sq: Square, now: f64) -> Cell {
+        match self.sliding(now) {
+            Some((m, _)) if m.from == sq => Cell::Empty,
+            Some(_) => self.before[sq],
+            None => self.board[sq],
+        }
+    }
+
+    // the piece that's sliding, which is already on its square in the replay board
+    pub fn sliding_cell(&self, now: f64) -> Option<(Cell, Square, Square, f32)> {
+        self.sliding(now).map(|(m, done)| (self.board[m.to], m.from, m.to, done))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::{Color, Piece};
+
+    #[test]
+    fn test_replay_steps() {
+        let board = LiBoard::from_notation("8 Ra1 a8,h8,h1 - - Q").unwrap();
+        let (_, path) = board.num_optimal_moves_to_star().unwrap();
+        let mut replay = Replay::new(&board, path.clone());
+        assert_eq!((0, 3), (replay.step(), replay.move_cnt()));
+        // the first move is played straight away, the rest a step apart
+        assert_eq!(Some(true), replay.tick(0.0));
+        assert_eq!(None, replay.tick(STEP_SECS / 2.0));
+        // the moved piece isn't drawn on either square while it slides
+        let first = path[0].clone();
+        assert_eq!((Cell::Empty, Cell::Star), (replay.cell(first.from, SLIDE_SECS / 2.0), replay.cell(first.to, SLIDE_SECS / 2.0)));
+        assert_eq!(Cell::Piece(Color::White, Piece::Rook), replay.cell(first.to, SLIDE_SECS));
+        assert_eq!(Some(true), replay.tick(STEP_SECS));
+        assert_eq!(Some(true), replay.tick(2.0 * STEP_SECS));
+        assert!(replay.is_done() && !replay.playing);
+        assert_eq!(3, replay.played().count());
+        assert!(board.squares().all(|sq| replay.cell(sq, 10.0) != Cell::Star));
+        // going back puts the stars back without sliding
+        replay.seek(1);
+        assert_eq!(1, replay.step());
+        assert_eq!(2, board.squares().filter(|&sq| replay.cell(sq, 0.0) == Cell::Star).count());
+    }
+}
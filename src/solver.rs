@@ -0,0 +1,1201 @@
+use crate::bitboard::{BitBoard, Mask, Tables};
+use crate::chess::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasherDefault, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// A search node. Squares are numbered like bitboard bits, and stars and enemies are bitboards of the squares they're still on.
+// Piece kinds are part of the state because pawns promote mid search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct State {
+    // Square and kind of each of the player's pieces, only the solver's piece_cnt first ones are used.
+    // They're kept sorted by square so swapping two pieces of the same kind gives the same state.
+    pieces: [(u8, Piece); MAX_PLAYER_PIECES],
+    stars: Mask,
+    enemies: Mask,
+}
+
+// one of the player's pieces, by its index in the state, moving to a square
+#[derive(Debug, Clone, Copy)]
+struct Step {
+    piece: usize,
+    to: u8,
+}
+
+// Multiply-rotate hasher for search states. The default SipHash dominates the search time otherwise.
+#[derive(Default)]
+struct StateHasher(u64);
+
+impl Hasher for StateHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.write_u64(b as u64);
+        }
+    }
+
+    // enum discriminants hash through here
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_u8(&mut self, n: u8) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 = (self.0.rotate_left(5) ^ n).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+
+    fn write_u128(&mut self, n: u128) {
+        self.write_u64(n as u64);
+        self.write_u64((n >> 64) as u64);
+    }
+}
+
+type StateSet = HashSet<State, BuildHasherDefault<StateHasher>>;
+type FastMap<K, V> = HashMap<K, V, BuildHasherDefault<StateHasher>>;
+
+// The parts of the board that don't change during a solve
+struct Solver {
+    tables: &'static Tables,
+    obstacles: Mask,
+    // where the stars and enemies start
+    stars: Mask,
+    enemies: Mask,
+    // enemy squares by piece
+    enemy_pieces: [Mask; 6],
+    // how many pieces the player has
+    piece_cnt: usize,
+    // the player's colour, which decides the pawn direction
+    color: Color,
+    promotion_piece: Piece,
+    // shortest move counts between squares on the obstacle only board, for each piece the player can be
+    distances: HashMap<Piece, Vec<Vec<u8>>>,
+    // attacked squares for each set of remaining enemies
+    attacks: FastMap<Mask, Mask>,
+    // minimum spanning tree weight for each (piece, remaining stars)
+    spanning_trees: FastMap<(Piece, Mask), usize>,
+    // set when nobody wants the answer anymore, searches give up as soon as they see it
+    cancelled: Arc<AtomicBool>,
+    // states the searches can still look at before giving up as if cancelled
+    budget: usize,
+}
+
+impl Solver {
+    fn new(board: &BitBoard) -> Solver {
+        let players = board.tables.squares(board.color_mask(Color::White));
+        let kinds: Vec<Piece> = players.filter_map(|sq| if let Cell::Piece(_, piece) = board.cell(sq) { Some(piece) } else { None }).collect();
+        let mut solver = Solver {
+            tables: board.tables,
+            obstacles: board.obstacles,
+            stars: board.stars,
+            enemies: board.color_mask(Color::Black),
+            enemy_pieces: board.pieces[Color::Black as usize],
+            piece_cnt: kinds.len(),
+            // the player always plays white
+            color: Color::White,
+            promotion_piece: board.promotion_piece,
+            distances: HashMap::new(),
+            attacks: FastMap::default(),
+            spanning_trees: FastMap::default(),
+            cancelled: Arc::default(),
+            budget: usize::MAX,
+        };
+        // pawn distances go through the promotion piece's, so it comes first
+        for piece in [board.promotion_piece].into_iter().chain(kinds) {
+            if !solver.distances.contains_key(&piece) {
+                let square_cnt = board.tables.size * board.tables.size;
+                let distances = (0..square_cnt).map(|sq| solver.distances_from(piece, sq as u8)).collect();
+                solver.distances.insert(piece, distances);
+            }
+        }
+        solver
+    }
+
+    // Breadth first search over the obstacle only board. Unreachable squares are u8::MAX.
+    // Pawns are let capture on every diagonal, and carry on as the promotion piece from the last rank.
+    fn distances_from(&self, piece: Piece, sq: u8) -> Vec<u8> {
+        let mut distances = vec![u8::MAX; self.tables.size * self.tables.size];
+        distances[sq as usize] = 0;
+        let mut queue = VecDeque::from([sq]);
+        while let Some(cur) = queue.pop_front() {
+            let mut targets = self.tables.moves(cur as usize, self.color, piece, 0, 0, self.obstacles);
+            if piece == Piece::Pawn {
+                targets |= self.tables.pawn[self.color as usize][cur as usize] & !self.obstacles;
+            }
+            while targets != 0 {
+                let target = targets.trailing_zeros() as usize;
+                targets &= targets - 1;
+                if distances[target] == u8::MAX {
+                    distances[target] = distances[cur as usize] + 1;
+                    queue.push_back(target as u8);
+                }
+            }
+        }
+        // a pawn that promotes to a pawn just stays one
+        if piece == Piece::Pawn && self.promotion_piece != Piece::Pawn {
+            let promoted = &self.distances[&self.promotion_piece];
+            let last_rank = if self.color == Color::White { 0 } else { self.tables.size - 1 };
+            for promotion in (0..self.tables.size).map(|j| last_rank * self.tables.size + j) {
+                if distances[promotion] == u8::MAX {
+                    continue;
+                }
+                for (target, &d) in promoted[promotion].iter().enumerate() {
+                    distances[target] = distances[target].min(distances[promotion].saturating_add(d));
+                }
+            }
+        }
+        distances
+    }
+
+    // Lower bound on the moves left. The moves have to join every star up to one of the pieces,
+    // and none of them are shorter than on the obstacle only board.
+    fn lower_bound(&mut self, state: &State) -> usize {
+        let pieces = &state.pieces[..self.piece_cnt];
+        if state.stars == 0 {
+            return 0;
+        }
+        let stars: Vec<usize> = self.tables.squares(state.stars).map(|sq| self.tables.index(sq)).collect();
+        if let [(sq, piece)] = *pieces {
+            // one piece has to reach some star and then tour the rest, so the tree over the stars only depends on them
+            let nearest = stars.iter().map(|&star| self.distances[&piece][sq as usize][star] as usize).min().unwrap();
+            if nearest == u8::MAX as usize {
+                return 0;
+            }
+            let tree = match self.spanning_trees.get(&(piece, state.stars)) {
+                Some(&tree) => tree,
+                None => {
+                    let mut closest = vec![usize::MAX; stars.len()];
+                    closest[0] = 0;
+                    let tree = spanning_tree_weight(&stars, closest, |a, b| self.tour_distance(piece, a, b));
+                    self.spanning_trees.insert((piece, state.stars), tree);
+                    tree
+                }
+            };
+            return nearest + tree;
+        }
+        // several pieces split the stars between them, so join the stars up to all the pieces at once
+        let closest = stars
+            .iter()
+            .map(|&star| pieces.iter().map(|&(sq, piece)| self.distances[&piece][sq as usize][star]).min().unwrap() as usize)
+            .collect();
+        spanning_tree_weight(&stars, closest, |a, b| pieces.iter().map(|&(_, piece)| self.tour_distance(piece, a, b)).min().unwrap())
+    }
+
+    // Fewest moves between two squares either way round for a piece of this kind.
+    // A pawn may have promoted by the time it gets there, so it gets the promotion piece's distance too.
+    fn tour_distance(&self, piece: Piece, a: usize, b: usize) -> usize {
+        let distances = &self.distances[&piece];
+        let mut distance = distances[a][b].min(distances[b][a]);
+        if piece == Piece::Pawn {
+            let promoted = &self.distances[&self.promotion_piece];
+            distance = distance.min(promoted[a][b]).min(promoted[b][a]);
+        }
+        distance as usize
+    }
+
+    // None if the player has no pieces, or more than a state can hold
+    fn start_state(&self, board: &BitBoard) -> Option<State> {
+        if self.piece_cnt == 0 || self.piece_cnt > MAX_PLAYER_PIECES {
+            return None;
+        }
+        let mut pieces = [(0, Piece::Pawn); MAX_PLAYER_PIECES];
+        for (slot, sq) in pieces.iter_mut().zip(board.tables.squares(board.color_mask(Color::White))) {
+            if let Cell::Piece(_, piece) = board.cell(sq) {
+                *slot = (board.tables.index(sq) as u8, piece);
+            }
+        }
+        Some(State {
+            pieces,
+            stars: self.stars,
+            enemies: self.enemies,
+        })
+    }
+
+    fn has_enemy(&self, state: &State, sq: u8) -> bool {
+        state.enemies & (1 << sq) != 0
+    }
+
+    // squares the player's pieces are standing on
+    fn player_squares(&self, state: &State) -> Mask {
+        state.pieces[..self.piece_cnt].iter().fold(0, |mask, &(sq, _)| mask | 1 << sq)
+    }
+
+    // squares guarded by the remaining enemies. Only enemies and obstacles block enemy attacks.
+    fn attacked(&mut self, state: &State) -> Mask {
+        if state.enemies == 0 {
+            return 0;
+        }
+        if let Some(attacked) = self.attacks.get(&state.enemies) {
+            return *attacked;
+        }
+        let remaining = self.enemy_pieces.map(|pieces| pieces & state.enemies);
+        let attacked = self.tables.guarded(&remaining, self.obstacles);
+        self.attacks.insert(state.enemies, attacked);
+        attacked
+    }
+
+    // fills steps with every legal move of every piece from this state
+    fn moves(&mut self, state: &State, steps: &mut Vec<Step>) {
+        steps.clear();
+        let attacked = self.attacked(state);
+        for piece in 0..self.piece_cnt {
+            let mut targets = self.reachable(state, piece) & !attacked;
+            while targets != 0 {
+                steps.push(Step {
+                    piece,
+                    to: targets.trailing_zeros() as u8,
+                });
+                targets &= targets - 1;
+            }
+        }
+    }
+
+    // every square the piece can reach from this state, guarded or not. The player's other pieces are in the way.
+    fn reachable(&self, state: &State, piece: usize) -> Mask {
+        let (sq, kind) = state.pieces[piece];
+        let walls = self.obstacles | self.player_squares(state);
+        self.tables.moves(sq as usize, self.color, kind, state.stars, state.enemies, walls)
+    }
+
+    // fills preds with every state that reaches this state in one move.
+    // Only valid for a single piece that moves the same way in both directions, which rules out pawns.
+    fn predecessors(&mut self, state: &State, preds: &mut Vec<State>) {
+        preds.clear();
+        let sq = state.pieces[0].0;
+        // the move either collected the star or captured the enemy on sq, or sq was already empty
+        let mut befores = vec![*state];
+        if self.stars & (1 << sq) != 0 {
+            befores.push(State {
+                stars: state.stars | 1 << sq,
+                ..*state
+            });
+        }
+        if self.enemies & (1 << sq) != 0 {
+            befores.push(State {
+                enemies: state.enemies | 1 << sq,
+                ..*state
+            });
+        }
+        for before in befores {
+            if self.attacked(&before) & (1 << sq) != 0 {
+                continue;
+            }
+            // the piece can't have been standing on a star or an enemy
+            let mut sources = self.reachable(&before, 0) & !before.stars & !before.enemies;
+            while sources != 0 {
+                let mut pred = before;
+                pred.pieces[0].0 = sources.trailing_zeros() as u8;
+                sources &= sources - 1;
+                preds.push(pred);
+            }
+        }
+    }
+
+    fn apply(&self, state: &State, step: Step) -> State {
+        let mut next = State {
+            stars: state.stars & !(1 << step.to),
+            enemies: state.enemies & !(1 << step.to),
+            ..*state
+        };
+        next.pieces[step.piece].0 = step.to;
+        // promote pawns that reach the last rank
+        let last_rank = if self.color == Color::White { 0 } else { self.tables.size - 1 };
+        if next.pieces[step.piece].1 == Piece::Pawn && self.tables.square(step.to as usize).i == last_rank {
+            next.pieces[step.piece].1 = self.promotion_piece;
+        }
+        next.pieces[..self.piece_cnt].sort_unstable_by_key(|&(sq, _)| sq);
+        next
+    }
+
+    fn to_move(&self, state: &State, step: Step) -> MovePiece {
+        MovePiece {
+            from: self.tables.square(state.pieces[step.piece].0 as usize),
+            to: self.tables.square(step.to as usize),
+        }
+    }
+
+    // The move that takes one state to the next. The pieces get reordered, so it goes by which square was left and which was taken.
+    fn move_between(&self, from: &State, to: &State) -> MovePiece {
+        let (before, after) = (self.player_squares(from), self.player_squares(to));
+        MovePiece {
+            from: self.tables.square((before & !after).trailing_zeros() as usize),
+            to: self.tables.square((after & !before).trailing_zeros() as usize),
+        }
+    }
+
+    // searches call this once for every state they look at, which also uses up the budget
+    fn is_cancelled(&mut self) -> bool {
+        if self.budget == 0 {
+            return true;
+        }
+        self.budget -= 1;
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    // Collects the nearest star over and over. Gives an upper bound on the optimal move count, or None if cancelled.
+    fn greedy_move_count(&mut self, start: State) -> Option<usize> {
+        let mut state = start;
+        let mut total = 0;
+        let mut targets = Vec::new();
+        while state.stars != 0 {
+            // breadth first search till any star is collected
+            let mut visited = StateSet::default();
+            visited.insert(state);
+            let mut queue = VecDeque::from([(state, 0)]);
+            let mut nearest = None;
+            'search: while let Some((cur, cur_move_count)) = queue.pop_front() {
+                if self.is_cancelled() {
+                    return None;
+                }
+                self.moves(&cur, &mut targets);
+                for &target in &targets {
+                    let next = self.apply(&cur, target);
+                    if next.stars != cur.stars {
+                        nearest = Some((next, cur_move_count + 1));
+                        break 'search;
+                    }
+                    if visited.insert(next) {
+                        queue.push_back((next, cur_move_count + 1));
+                    }
+                }
+            }
+            // greedy can walk a pawn into a dead end even when the board is solvable
+            let (next, move_count) = nearest?;
+            state = next;
+            total += move_count;
+        }
+        Some(total)
+    }
+}
+
+// Prim's algorithm over the squares, starting from how far each one is from what's already in the tree
+fn spanning_tree_weight(squares: &[usize], mut closest: Vec<usize>, distance: impl Fn(usize, usize) -> usize) -> usize {
+    let mut weight = 0;
+    let mut in_tree = vec![false; squares.len()];
+    for _ in 0..squares.len() {
+        let next = (0..squares.len()).filter(|&idx| !in_tree[idx]).min_by_key(|&idx| closest[idx]).unwrap();
+        in_tree[next] = true;
+        weight += closest[next];
+        for idx in 0..squares.len() {
+            if !in_tree[idx] {
+                closest[idx] = closest[idx].min(distance(squares[next], squares[idx]));
+            }
+        }
+    }
+    weight
+}
+
+// How the optimal route is searched for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SolverStrategy {
+    // breadth first search over every state
+    Bfs,
+    // breadth first search pruned by a move bound that grows from a lower bound until a solution fits
+    BoundedBfs,
+    // breadth first search from the start and from every finished state at once
+    Bidirectional,
+    // best first search using the lower bound on moves left as the heuristic, usually the fewest expansions
+    #[default]
+    AStar,
+}
+
+impl SolverStrategy {
+    pub fn name(self) -> &'static str {
+        match self {
+            SolverStrategy::Bfs => "BFS",
+            SolverStrategy::BoundedBfs => "Bounded BFS",
+            SolverStrategy::Bidirectional => "Bidirectional BFS",
+            SolverStrategy::AStar => "A*",
+        }
+    }
+}
+
+// Search counters for comparing strategies
+#[derive(Debug, Clone, Default)]
+pub struct SolverStats {
+    // states whose moves were generated
+    pub expanded: usize,
+    // states produced by those moves, duplicates included
+    pub generated: usize,
+    // the search that actually ran, since some strategies hand boards they can't do to another one
+    pub strategy: SolverStrategy,
+}
+
+fn to_path(moves: impl IntoIterator<Item = MovePiece>) -> SolutionPath {
+    moves.into_iter().fold(SolutionPath::new(), |path, m| path.push_back(m))
+}
+
+// walk back up the parent links to recover the moves
+fn path_to(solver: &Solver, nodes: &[(State, usize)], node: usize) -> SolutionPath {
+    let mut moves = Vec::new();
+    let mut cur = node;
+    while cur != 0 {
+        let parent = nodes[cur].1;
+        moves.push(solver.move_between(&nodes[parent].0, &nodes[cur].0));
+        cur = parent;
+    }
+    to_path(moves.into_iter().rev())
+}
+
+// Breadth first search that drops every state which can't collect the remaining stars within bound moves
+fn bounded_search(solver: &mut Solver, start: State, bound: usize, stats: &mut SolverStats) -> Option<(i8, SolutionPath)> {
+    // every discovered state with the index of the state it was reached from
+    let mut nodes: Vec<(State, usize)> = vec![(start, 0)];
+    let mut visited = StateSet::default();
+    visited.insert(start);
+    let mut current_queue = VecDeque::from([(0, 0)]);
+    let mut targets = Vec::new();
+
+    while let Some((node, cur_move_count)) = current_queue.pop_front() {
+        if solver.is_cancelled() {
+            return None;
+        }
+        let state = nodes[node].0;
+        if state.stars == 0 {
+            return Some((cur_move_count as i8, path_to(solver, &nodes, node)));
+        }
+        stats.expanded += 1;
+        solver.moves(&state, &mut targets);
+        stats.generated += targets.len();
+        for &target in &targets {
+            let next = solver.apply(&state, target);
+            // Every move collects at most one star, which is much cheaper to check than the full lower bound.
+            // A state dropped here would be dropped at any later depth too, so it can be marked visited.
+            if cur_move_count + 1 + next.stars.count_ones() as usize <= bound && visited.insert(next) && cur_move_count + 1 + solver.lower_bound(&next) <= bound {
+                nodes.push((next, node));
+                current_queue.push_back((nodes.len() - 1, cur_move_count + 1));
+            }
+        }
+    }
+    None
+}
+
+// States are pruned against a move bound that starts at a lower bound and grows until a solution fits,
+// which never passes the greedy tour's length since that tour fits by definition.
+fn deepening_search(solver: &mut Solver, start: State, stats: &mut SolverStats) -> Option<(i8, SolutionPath)> {
+    match solver.greedy_move_count(start) {
+        Some(greedy) => (solver.lower_bound(&start)..=greedy).find_map(|bound| bounded_search(solver, start, bound, stats)),
+        // greedy can get stuck even on solvable boards, so fall back to a full search
+        None => {
+            stats.strategy = SolverStrategy::Bfs;
+            bounded_search(solver, start, usize::MAX, stats)
+        }
+    }
+}
+
+// A* search that can stop when the solver is cancelled or out of budget and carry on from its open list later
+struct AStar {
+    nodes: Vec<(State, usize)>,
+    // fewest moves each state has been reached in so far
+    best: FastMap<State, usize>,
+    // lowest estimate first, then the deepest state since it's closest to being done
+    open: BinaryHeap<Reverse<(usize, Reverse<usize>, usize)>>,
+}
+
+impl AStar {
+    fn new(solver: &mut Solver, start: State) -> AStar {
+        let mut best = FastMap::default();
+        best.insert(start, 0);
+        AStar {
+            nodes: vec![(start, 0)],
+            best,
+            open: BinaryHeap::from([Reverse((solver.lower_bound(&start), Reverse(0), 0))]),
+        }
+    }
+
+    // Some with the optimal route, or with None if there isn't one, once the search is over.
+    // None if it stopped part way, and then the next call picks up where it left off.
+    fn resume(&mut self, solver: &mut Solver, stats: &mut SolverStats) -> Option<Option<(i8, SolutionPath)>> {
+        let mut targets = Vec::new();
+        while let Some(&Reverse((_, Reverse(cur_move_count), node))) = self.open.peek() {
+            if solver.is_cancelled() {
+                return None;
+            }
+            self.open.pop();
+            let state = self.nodes[node].0;
+            // the lower bound isn't consistent, so states can be reopened and stale entries skipped
+            if self.best[&state] < cur_move_count {
+                continue;
+            }
+            if state.stars == 0 {
+                return Some(Some((cur_move_count as i8, path_to(solver, &self.nodes, node))));
+            }
+            stats.expanded += 1;
+            solver.moves(&state, &mut targets);
+            stats.generated += targets.len();
+            for &target in &targets {
+                let next = solver.apply(&state, target);
+                if self.best.get(&next).map_or(true, |&b| cur_move_count + 1 < b) {
+                    self.best.insert(next, cur_move_count + 1);
+                    self.nodes.push((next, node));
+                    let estimate = cur_move_count + 1 + solver.lower_bound(&next);
+                    self.open.push(Reverse((estimate, Reverse(cur_move_count + 1), self.nodes.len() - 1)));
+                }
+            }
+        }
+        Some(None)
+    }
+}
+
+fn astar_search(solver: &mut Solver, start: State, stats: &mut SolverStats) -> Option<(i8, SolutionPath)> {
+    AStar::new(solver, start).resume(solver, stats).flatten()
+}
+
+// Most enemies the bidirectional search seeds goals for. Every subset of them is a way to finish, so it doubles with each one.
+const MAX_BIDIRECTIONAL_ENEMIES: u32 = 4;
+
+// Searches a layer at a time from the start and from every state with all the stars collected, until the two meet.
+// Pawns can't be searched backwards and several pieces have too many finishing positions, so they get a plain breadth first search.
+// Boards with lots of enemies have too many finishing positions as well and get A*.
+fn bidirectional_search(solver: &mut Solver, start: State, stats: &mut SolverStats) -> Option<(i8, SolutionPath)> {
+    if solver.piece_cnt > 1 || start.pieces[0].1 == Piece::Pawn {
+        stats.strategy = SolverStrategy::Bfs;
+        return bounded_search(solver, start, usize::MAX, stats);
+    }
+    if start.enemies.count_ones() > MAX_BIDIRECTIONAL_ENEMIES {
+        stats.strategy = SolverStrategy::AStar;
+        return astar_search(solver, start, stats);
+    }
+    if start.stars == 0 {
+        return Some((0, SolutionPath::new()));
+    }
+    // state -> (moves from the start, previous state) and state -> (moves to a goal, next state)
+    let mut forward: FastMap<State, (usize, State)> = FastMap::default();
+    let mut backward: FastMap<State, (usize, State)> = FastMap::default();
+    forward.insert(start, (0, start));
+    let mut forward_layer = vec![start];
+    let mut backward_layer = Vec::new();
+    // any square and any set of captured enemies can finish the round
+    for sq in 0..(solver.tables.size * solver.tables.size) as u8 {
+        if solver.obstacles & (1 << sq) != 0 {
+            continue;
+        }
+        // walk every subset of the starting enemies
+        let mut enemies = start.enemies;
+        loop {
+            let mut goal = State { stars: 0, enemies, ..start };
+            goal.pieces[0].0 = sq;
+            if !solver.has_enemy(&goal, sq) {
+                backward.insert(goal, (0, goal));
+                backward_layer.push(goal);
+            }
+            if enemies == 0 {
+                break;
+            }
+            enemies = (enemies - 1) & start.enemies;
+        }
+    }
+
+    let mut neighbours = Vec::new();
+    let mut targets = Vec::new();
+    while !forward_layer.is_empty() && !backward_layer.is_empty() {
+        // grow whichever side has the smaller frontier
+        let is_forward = forward_layer.len() <= backward_layer.len();
+        let (layer, this_side, other_side) = if is_forward {
+            (&mut forward_layer, &mut forward, &backward)
+        } else {
+            (&mut backward_layer, &mut backward, &forward)
+        };
+        let mut next_layer = Vec::new();
+        let mut meeting: Option<(usize, State)> = None;
+        for state in layer.drain(..) {
+            if solver.is_cancelled() {
+                return None;
+            }
+            stats.expanded += 1;
+            if is_forward {
+                solver.moves(&state, &mut targets);
+                neighbours.clear();
+                neighbours.extend(targets.iter().map(|&target| solver.apply(&state, target)));
+            } else {
+                solver.predecessors(&state, &mut neighbours);
+            }
+            stats.generated += neighbours.len();
+            let depth = this_side[&state].0 + 1;
+            for &next in &neighbours {
+                if this_side.contains_key(&next) {
+                    continue;
+                }
+                this_side.insert(next, (depth, state));
+                next_layer.push(next);
+                if let Some(&(other_depth, _)) = other_side.get(&next) {
+                    if meeting.map_or(true, |(total, _)| depth + other_depth < total) {
+                        meeting = Some((depth + other_depth, next));
+                    }
+                }
+            }
+        }
+        *layer = next_layer;
+
+        if let Some((total, meet)) = meeting {
+            let mut moves = Vec::new();
+            let mut cur = meet;
+            while cur != start {
+                let prev = forward[&cur].1;
+                moves.push(solver.move_between(&prev, &cur));
+                cur = prev;
+            }
+            moves.reverse();
+            let mut cur = meet;
+            while backward[&cur].0 != 0 {
+                let next = backward[&cur].1;
+                moves.push(solver.move_between(&cur, &next));
+                cur = next;
+            }
+            return Some((total as i8, to_path(moves)));
+        }
+    }
+    None
+}
+
+// What a solve hands back: the optimal move count and path if there is one, and how much searching it took
+pub type SolveResult = (Option<(i8, SolutionPath)>, SolverStats);
+
+fn solve(solver: &mut Solver, start: State, strategy: SolverStrategy) -> SolveResult {
+    let mut stats = SolverStats { strategy, ..Default::default() };
+    let solution = match strategy {
+        SolverStrategy::Bfs => bounded_search(solver, start, usize::MAX, &mut stats),
+        SolverStrategy::BoundedBfs => deepening_search(solver, start, &mut stats),
+        SolverStrategy::Bidirectional => bidirectional_search(solver, start, &mut stats),
+        SolverStrategy::AStar => astar_search(solver, start, &mut stats),
+    };
+    (solution, stats)
+}
+
+// Most states the greedy solvability check looks at
+const GREEDY_CHECK_BUDGET: usize = 100_000;
+
+// How many optimal routes get listed. The count still covers all of them.
+const MAX_LISTED_ROUTES: usize = 32;
+
+// Every optimal way to collect the stars
+#[derive(Debug, Clone)]
+pub struct OptimalRoutes {
+    pub move_cnt: i8,
+    // number of different optimal move sequences
+    pub count: u64,
+    // the first MAX_LISTED_ROUTES of them
+    pub paths: Vec<SolutionPath>,
+}
+
+// Number of ways to collect the remaining stars in exactly remaining moves, or None if cancelled.
+// States that can't finish in time are cut with the lower bound, so this only walks optimal routes and their near misses.
+fn count_routes(solver: &mut Solver, state: State, remaining: usize, memo: &mut FastMap<(State, usize), u64>) -> Option<u64> {
+    if state.stars == 0 {
+        return Some((remaining == 0) as u64);
+    }
+    if state.stars.count_ones() as usize > remaining || solver.lower_bound(&state) > remaining {
+        return Some(0);
+    }
+    if let Some(&count) = memo.get(&(state, remaining)) {
+        return Some(count);
+    }
+    if solver.is_cancelled() {
+        return None;
+    }
+    let mut targets = Vec::new();
+    solver.moves(&state, &mut targets);
+    let mut count: u64 = 0;
+    for target in targets {
+        let next = solver.apply(&state, target);
+        count = count.saturating_add(count_routes(solver, next, remaining - 1, memo)?);
+    }
+    memo.insert((state, remaining), count);
+    Some(count)
+}
+
+// Follows the counted states to list routes, skipping every move with no route behind it
+fn collect_routes(solver: &mut Solver, state: State, remaining: usize, memo: &mut FastMap<(State, usize), u64>, route: &mut Vec<MovePiece>, routes: &mut Vec<Vec<MovePiece>>) {
+    if state.stars == 0 {
+        routes.push(route.clone());
+        return;
+    }
+    let mut targets = Vec::new();
+    solver.moves(&state, &mut targets);
+    for target in targets {
+        if routes.len() == MAX_LISTED_ROUTES {
+            return;
+        }
+        let next = solver.apply(&state, target);
+        // everything below a fully counted state is already counted, so this can't be cancelled
+        if count_routes(solver, next, remaining - 1, memo).unwrap_or(0) > 0 {
+            route.push(solver.to_move(&state, target));
+            collect_routes(solver, next, remaining - 1, memo, route, routes);
+            route.pop();
+        }
+    }
+}
+
+// Optimal routes with plain move lists, which unlike SolutionPath can be sent between threads
+struct RawRoutes {
+    move_cnt: i8,
+    count: u64,
+    paths: Vec<Vec<MovePiece>>,
+}
+
+impl RawRoutes {
+    fn into_routes(self) -> OptimalRoutes {
+        OptimalRoutes {
+            move_cnt: self.move_cnt,
+            count: self.count,
+            paths: self.paths.into_iter().map(to_path).collect(),
+        }
+    }
+}
+
+// Where a RouteSolve has got to
+enum RoutePhase {
+    // finding the optimal move count. Only A* can stop part way, so the other strategies have no search to keep.
+    Searching(Option<AStar>),
+    // counting the routes of that length, keeping every count that got finished
+    Counting { move_cnt: i8, memo: FastMap<(State, usize), u64> },
+}
+
+// Finds the optimal move count with the strategy, then counts and lists the routes of that length.
+// It runs in steps that each look at a budget of states, so the web build can spread a solve over several frames.
+struct RouteSolve {
+    solver: Solver,
+    // None if there's no piece to move
+    start: Option<State>,
+    strategy: SolverStrategy,
+    // only cover finding the move count
+    stats: SolverStats,
+    phase: RoutePhase,
+}
+
+impl RouteSolve {
+    fn new(board: &BitBoard, strategy: SolverStrategy, cancelled: Arc<AtomicBool>) -> RouteSolve {
+        let mut solver = Solver::new(board);
+        solver.cancelled = cancelled;
+        let start = solver.start_state(board);
+        let search = match (strategy, start) {
+            (SolverStrategy::AStar, Some(start)) => Some(AStar::new(&mut solver, start)),
+            _ => None,
+        };
+        RouteSolve {
+            solver,
+            start,
+            strategy,
+            stats: SolverStats { strategy, ..Default::default() },
+            phase: RoutePhase::Searching(search),
+        }
+    }
+
+    // Carries on with the solve until it's done or has looked at budget states, and takes the ones it looked at off the budget.
+    // The routes and stats once the solve is done, None if it stopped part way.
+    fn step(&mut self, budget: &mut usize) -> Option<(Option<RawRoutes>, SolverStats)> {
+        let Some(start) = self.start else {
+            return Some((None, SolverStats::default()));
+        };
+        self.solver.budget = *budget;
+        let result = self.run(start);
+        *budget = self.solver.budget;
+        result
+    }
+
+    fn run(&mut self, start: State) -> Option<(Option<RawRoutes>, SolverStats)> {
+        if let RoutePhase::Searching(search) = &mut self.phase {
+            let solution = match search {
+                Some(astar) => astar.resume(&mut self.solver, &mut self.stats)?,
+                None => {
+                    let (solution, stats) = solve(&mut self.solver, start, self.strategy);
+                    self.stats = stats;
+                    solution
+                }
+            };
+            let Some((move_cnt, _)) = solution else {
+                return Some((None, self.stats.clone()));
+            };
+            self.phase = RoutePhase::Counting { move_cnt, memo: FastMap::default() };
+        }
+        let RoutePhase::Counting { move_cnt, memo } = &mut self.phase else {
+            unreachable!("the search phase either finished or returned");
+        };
+        let count = count_routes(&mut self.solver, start, *move_cnt as usize, memo)?;
+        let mut paths = Vec::new();
+        collect_routes(&mut self.solver, start, *move_cnt as usize, memo, &mut Vec::new(), &mut paths);
+        Some((Some(RawRoutes { move_cnt: *move_cnt, count, paths }), self.stats.clone()))
+    }
+}
+
+// A whole RouteSolve in one go, giving up with no routes after looking at budget states
+fn solve_routes(board: &BitBoard, strategy: SolverStrategy, cancelled: Arc<AtomicBool>, budget: &mut usize) -> (Option<RawRoutes>, SolverStats) {
+    let mut route_solve = RouteSolve::new(board, strategy, cancelled);
+    route_solve.step(budget).unwrap_or_else(|| (None, route_solve.stats.clone()))
+}
+
+impl LiBoard {
+    // calculates the number of moves to optimally collect all stars, or None if they can't all be collected
+    // Solves without a budget, which only the tests can wait on. The app solves in a SolveTask and the generator within a budget.
+    #[cfg(test)]
+    pub fn num_optimal_moves_to_star(&self) -> Option<(i8, SolutionPath)> {
+        self.solve_with(SolverStrategy::default()).0
+    }
+
+    // Same as num_optimal_moves_to_star with a chosen search strategy. Every strategy finds an optimal route,
+    // they only differ in how much of the board they look at, which the stats report.
+    // The search runs over (piece squares, stars left) states rather than whole boards.
+    #[cfg(test)]
+    pub fn solve_with(&self, strategy: SolverStrategy) -> SolveResult {
+        BitBoard::from(self).solve_with(strategy)
+    }
+
+    // the optimal move count and how many different optimal routes there are, or None if the stars can't all be collected
+    // solves without a budget like num_optimal_moves_to_star
+    #[cfg(test)]
+    pub fn optimal_route_count(&self) -> Option<(i8, u64)> {
+        let mut budget = usize::MAX;
+        self.optimal_route_count_within(&mut budget)
+    }
+
+    // Same as optimal_route_count, but gives up with None once it's looked at budget search states.
+    // What it looked at is taken off the budget, so one budget can be shared by several solves.
+    pub fn optimal_route_count_within(&self, budget: &mut usize) -> Option<(i8, u64)> {
+        let routes = solve_routes(&BitBoard::from(self), SolverStrategy::default(), Arc::default(), budget).0?;
+        Some((routes.move_cnt, routes.count))
+    }
+
+    // Whether collecting the nearest star over and over clears the board, which is much cheaper than an optimal solve.
+    // Only pawns can walk into a dead end, so for every other piece this is the same as the board being solvable.
+    // A star nobody can reach makes the search look at every position first, so boards that slow to check count as not solvable.
+    pub fn is_greedy_solvable(&self) -> bool {
+        let bitboard = BitBoard::from(self);
+        let mut solver = Solver::new(&bitboard);
+        solver.budget = GREEDY_CHECK_BUDGET;
+        solver.start_state(&bitboard).and_then(|start| solver.greedy_move_count(start)).is_some()
+    }
+}
+
+// The solver works on bitboards, LiBoards get converted first
+#[cfg(test)]
+impl BitBoard {
+    pub fn solve_with(&self, strategy: SolverStrategy) -> SolveResult {
+        let mut solver = Solver::new(self);
+        match solver.start_state(self) {
+            Some(start) => solve(&mut solver, start, strategy),
+            // no piece to move
+            None => (None, SolverStats::default()),
+        }
+    }
+}
+
+// Most states the web build looks at per poll, so a frame never stalls for long
+#[cfg(target_arch = "wasm32")]
+const POLL_BUDGET: usize = 2_000;
+
+// A solve running in the background so the UI can keep drawing. Dropping the task cancels the solve.
+// Native builds run the solve on its own thread. The web build has no threads, so each poll runs a bit more of the
+// solve on the UI thread. Only A* can stop part way, the other strategies still run in one go there.
+pub struct SolveTask {
+    cancelled: Arc<AtomicBool>,
+    #[cfg(not(target_arch = "wasm32"))]
+    receiver: std::sync::mpsc::Receiver<(Option<RawRoutes>, SolverStats)>,
+    #[cfg(target_arch = "wasm32")]
+    route_solve: RouteSolve,
+}
+
+impl SolveTask {
+    pub fn spawn(board: &LiBoard, strategy: SolverStrategy) -> SolveTask {
+        let board = BitBoard::from(board);
+        #[cfg(not(target_arch = "wasm32"))]
+        return Self::spawn_job(move |cancelled| {
+            // background solves run till they're done or cancelled
+            let mut budget = usize::MAX;
+            solve_routes(&board, strategy, cancelled, &mut budget)
+        });
+        #[cfg(target_arch = "wasm32")]
+        {
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let route_solve = RouteSolve::new(&board, strategy, cancelled.clone());
+            SolveTask { cancelled, route_solve }
+        }
+    }
+
+    // runs the job on its own thread, handing it the flag that's set once nobody wants the result
+    #[cfg(not(target_arch = "wasm32"))]
+    fn spawn_job(job: impl FnOnce(Arc<AtomicBool>) -> (Option<RawRoutes>, SolverStats) + Send + 'static) -> SolveTask {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let flag = cancelled.clone();
+        std::thread::spawn(move || {
+            // fails if the task was dropped, and then nobody wants the result
+            let _ = sender.send(job(flag));
+        });
+        SolveTask { cancelled, receiver }
+    }
+
+    // the optimal routes and search stats once the solve is done, None while it's still running
+    pub fn poll(&mut self) -> Option<(Option<OptimalRoutes>, SolverStats)> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let result = match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(std::sync::mpsc::TryRecvError::Empty) => None,
+            // the solver thread panicked, so finish with no solution rather than waiting forever
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => Some((None, SolverStats::default())),
+        };
+        #[cfg(target_arch = "wasm32")]
+        let result = {
+            // only A* keeps its place when it runs out of budget, the others would give up
+            let mut budget = if self.route_solve.strategy == SolverStrategy::AStar { POLL_BUDGET } else { usize::MAX };
+            self.route_solve.step(&mut budget)
+        };
+        result.map(|(routes, stats)| (routes.map(RawRoutes::into_routes), stats))
+    }
+}
+
+impl Drop for SolveTask {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // shorthands for board literals
+    const E: Cell = Cell::Empty;
+    const S: Cell = Cell::Star;
+    const WR: Cell = Cell::Piece(Color::White, Piece::Rook);
+
+    // block until the background solve is done
+    fn wait_for(task: &mut SolveTask) -> (Option<OptimalRoutes>, SolverStats) {
+        loop {
+            if let Some(result) = task.poll() {
+                return result;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+    }
+
+    // plays the path from the start, checking every move, and checks it collects every star
+    fn assert_path_replays(board: &LiBoard, path: &SolutionPath) {
+        let mut replay = board.clone();
+        for m in path {
+            assert!(replay.validate_move(m).is_ok(), "{:?} is invalid", m);
+            replay.update_board(m);
+        }
+        assert!(!replay.board.iter().flatten().any(|&cell| cell == S));
+    }
+
+    #[test]
+    fn test_optimal_calc_many_stars() {
+        // the rook has to stop on every star of the U shape
+        let board = LiBoard {
+            board: vec![
+                vec![WR, S, S, S, S, S, S, S],
+                vec![E, E, E, E, E, E, E, S],
+                vec![E, E, E, E, E, E, E, S],
+                vec![E, E, E, E, E, E, E, S],
+                vec![E, E, E, E, E, E, E, S],
+                vec![E, E, E, E, E, E, E, S],
+                vec![E, E, E, E, E, E, E, S],
+                vec![S, S, S, S, S, S, S, S],
+            ],
+            num_star_cnt: 21,
+            pieces: vec![Square::new(0, 0)],
+            promotion_piece: Piece::Queen,
+        };
+        assert_eq!(21, board.num_optimal_moves_to_star().unwrap().0)
+    }
+
+    #[test]
+    fn test_solution_path_replays() {
+        for size in BOARD_SIZES {
+            for piece in [Piece::Queen, Piece::Knight, Piece::Rook, Piece::Bishop, Piece::King, Piece::Pawn] {
+                let limits = CountLimits::for_size(size);
+                let board = LiBoard::from_options_seeded(
+                    &BoardOptions {
+                        size,
+                        star_cnt: 5,
+                        choice_piece: piece,
+                        enemy_cnt: 2.min(limits.enemies),
+                        obstacle_cnt: 3.min(limits.obstacles),
+                        ..Default::default()
+                    },
+                    1,
+                );
+                assert_eq!(size, board.size());
+                let (cnt, path) = board.num_optimal_moves_to_star().unwrap();
+                assert_eq!(cnt as usize, path.len());
+                assert_path_replays(&board, &path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_solver_strategies_agree() {
+        for piece in [Piece::Queen, Piece::Knight, Piece::Rook, Piece::Bishop, Piece::King, Piece::Pawn] {
+            for seed in 0..3 {
+                let board = LiBoard::from_options_seeded(
+                    &BoardOptions {
+                        star_cnt: 5,
+                        choice_piece: piece,
+                        enemy_cnt: 2,
+                        obstacle_cnt: 3,
+                        ..Default::default()
+                    },
+                    seed,
+                );
+                let optimal = board.num_optimal_moves_to_star().unwrap().0;
+                for strategy in [SolverStrategy::Bfs, SolverStrategy::BoundedBfs, SolverStrategy::Bidirectional] {
+                    let (solution, stats) = board.solve_with(strategy);
+                    let (cnt, path) = solution.unwrap();
+                    assert_eq!(optimal, cnt, "{:?} disagrees", strategy);
+                    assert!(stats.expanded > 0);
+                    assert_path_replays(&board, &path);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bidirectional_many_enemies() {
+        // ten enemies would be over a thousand goal subsets for every square
+        let board = LiBoard::from_notation("8 Qa1 a4,d1,h3 - Pc5,Pd5,Pe5,Pf5,Pg5,Ph5,Pc7,Pd7,Pe7,Pf7 Q").unwrap();
+        let optimal = board.num_optimal_moves_to_star().unwrap().0;
+        let (solution, stats) = board.solve_with(SolverStrategy::Bidirectional);
+        assert_eq!(Some(optimal), solution.map(|(cnt, _)| cnt));
+        // the stats say it was handed to A*
+        assert_eq!(SolverStrategy::AStar, stats.strategy);
+    }
+
+    #[test]
+    fn test_extra_piece_boards_replay() {
+        for extra_pieces in [vec![Piece::Knight], vec![Piece::Bishop, Piece::King], vec![Piece::Rook, Piece::Queen]] {
+            let board = LiBoard::from_options_seeded(
+                &BoardOptions {
+                    star_cnt: 5,
+                    choice_piece: Piece::Pawn,
+                    extra_pieces: extra_pieces.clone(),
+                    enemy_cnt: 2,
+                    obstacle_cnt: 3,
+                    ..Default::default()
+                },
+                1,
+            );
+            assert_eq!(extra_pieces.len() + 1, board.pieces.len());
+            let optimal = board.num_optimal_moves_to_star().unwrap().0;
+            // plain breadth first search, which the bidirectional one falls back to here, is too slow over several pieces
+            for strategy in [SolverStrategy::AStar, SolverStrategy::BoundedBfs] {
+                let (cnt, path) = board.solve_with(strategy).0.unwrap();
+                assert_eq!(optimal, cnt, "{:?} disagrees", strategy);
+                assert_path_replays(&board, &path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_solve_budget() {
+        let board = LiBoard::from_notation("8 Qa1,Nb1 a8,h8,h1,d4,e6,c7 - -").unwrap();
+        let solved = board.optimal_route_count();
+        // running out gives up and uses the whole budget
+        let mut budget = 10;
+        assert_eq!(None, board.optimal_route_count_within(&mut budget));
+        assert_eq!(0, budget);
+        // enough of it gives the same answer and takes off what was looked at
+        budget = 1_000_000;
+        assert_eq!(solved, board.optimal_route_count_within(&mut budget));
+        assert!(budget > 0 && budget < 1_000_000);
+    }
+
+    #[test]
+    fn test_route_solve_resumes() {
+        let board = BitBoard::from(&LiBoard::from_notation("8 Qa1,Nb1 a8,h8,h1,d4,e6,c7 - -").unwrap());
+        let mut budget = usize::MAX;
+        let (whole, whole_stats) = solve_routes(&board, SolverStrategy::AStar, Arc::default(), &mut budget);
+        let whole = whole.unwrap();
+        // small steps pause and pick up where they left off, ending with the same routes and search
+        let mut route_solve = RouteSolve::new(&board, SolverStrategy::AStar, Arc::default());
+        let mut steps = 1;
+        let (stepped, stepped_stats) = loop {
+            let mut budget = 50;
+            match route_solve.step(&mut budget) {
+                Some(result) => break result,
+                None => steps += 1,
+            }
+        };
+        let stepped = stepped.unwrap();
+        assert!(steps > 1);
+        assert_eq!((whole.move_cnt, whole.count), (stepped.move_cnt, stepped.count));
+        assert_eq!(format!("{:?}", whole.paths), format!("{:?}", stepped.paths));
+        assert_eq!(whole_stats.expanded, stepped_stats.expanded);
+    }
+
+    #[test]
+    fn test_background_solve_panic_finishes() {
+        let mut task = SolveTask::spawn_job(|_| panic!("injected solver failure"));
+        assert!(wait_for(&mut task).0.is_none());
+    }
+
+    #[test]
+    fn test_pawn_promoting_to_pawn() {
+        // settings the app never picks still solve, the pawn just doesn't change
+        let mut board = LiBoard::from_notation("8 Pd7 d8,c5 - -").unwrap();
+        board.promotion_piece = Piece::Pawn;
+        assert_eq!(None, board.num_optimal_moves_to_star().map(|(move_cnt, _)| move_cnt));
+        // a double push from the second rank
+        board = LiBoard::from_notation("8 Pd2 d4 - -").unwrap();
+        board.promotion_piece = Piece::Pawn;
+        assert_eq!(Some(1), wait_for(&mut SolveTask::spawn(&board, SolverStrategy::default())).0.map(|routes| routes.move_cnt));
+    }
+
+    #[test]
+    fn test_background_solve_matches() {
+        let board = LiBoard::from_options_seeded(
+            &BoardOptions {
+                star_cnt: 8,
+                enemy_cnt: 2,
+                obstacle_cnt: 3,
+                ..Default::default()
+            },
+            1,
+        );
+        let routes = wait_for(&mut SolveTask::spawn(&board, SolverStrategy::default())).0.unwrap();
+        assert_eq!(board.num_optimal_moves_to_star().unwrap().0, routes.move_cnt);
+    }
+
+    #[test]
+    fn test_optimal_route_count() {
+        let mut board = LiBoard {
+            board: vec![vec![E; 8]; 8],
+            num_star_cnt: 1,
+            pieces: vec![Square::new(0, 0)],
+            promotion_piece: Piece::Queen,
+        };
+        board[Square::new(0, 0)] = WR;
+        board[Square::new(7, 7)] = S;
+        // through either corner
+        let routes = wait_for(&mut SolveTask::spawn(&board, SolverStrategy::default())).0.unwrap();
+        assert_eq!((2, 2, 2), (routes.move_cnt, routes.count, routes.paths.len()));
+        // a second star in one corner leaves a single route
+        board[Square::new(0, 7)] = S;
+        board.num_star_cnt = 2;
+        let routes = wait_for(&mut SolveTask::spawn(&board, SolverStrategy::default())).0.unwrap();
+        assert_eq!((2, 1), (routes.move_cnt, routes.count));
+        // every listed route collects the stars
+        board = LiBoard::from_options_seeded(
+            &BoardOptions {
+                star_cnt: 6,
+                choice_piece: Piece::King,
+                enemy_cnt: 2,
+                obstacle_cnt: 3,
+                ..Default::default()
+            },
+            1,
+        );
+        let routes = wait_for(&mut SolveTask::spawn(&board, SolverStrategy::default())).0.unwrap();
+        assert!(routes.count >= routes.paths.len() as u64);
+        for path in &routes.paths {
+            assert_eq!(routes.move_cnt as usize, path.len());
+            assert_path_replays(&board, path);
+        }
+    }
+}
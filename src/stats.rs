@@ -0,0 +1,176 @@
+use crate::chess::Piece;
+use serde::{Deserialize, Serialize};
+
+// pieces in the order the stats window lists them
+const PIECES: [Piece; 6] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight, Piece::King, Piece::Pawn];
+
+// One finished round
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoundRecord {
+    // the main piece, as it was at the start of the round
+    pub piece: Piece,
+    pub star_cnt: i8,
+    // None if the board couldn't be solved
+    pub optimal_moves: Option<i8>,
+    pub moves: i8,
+    pub secs: f32,
+    pub timed: bool,
+    // moves were taken back during the round
+    #[serde(default)]
+    pub undone: bool,
+    // tries at the board so far, 1 unless it was retried
+    #[serde(default = "first_attempt")]
+    pub attempt: u32,
+}
+
+fn first_attempt() -> u32 {
+    1
+}
+
+impl RoundRecord {
+    // moves over the optimal count, None if it isn't known
+    pub fn excess_moves(&self) -> Option<i8> {
+        self.optimal_moves.map(|optimal| self.moves - optimal)
+    }
+
+    pub fn is_perfect(&self) -> bool {
+        self.excess_moves() == Some(0)
+    }
+
+    // Points for the round and the streak it leaves the player on. Perfect is worth 100 and a move over 10.
+    // Taking moves back halves the points and every retry divides them again. Only a clean first attempt grows the streak.
+    pub fn score(&self, streak: u64) -> (u64, u64) {
+        let divisor = u64::from(self.attempt.max(1)) * if self.undone { 2 } else { 1 };
+        match self.excess_moves() {
+            Some(0) if !self.undone && self.attempt == 1 => (100, streak + 1),
+            Some(0) => (100 / divisor, streak),
+            Some(1) => (10 / divisor, 0),
+            _ => (0, 0),
+        }
+    }
+}
+
+// Every finished round, oldest first
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RoundHistory {
+    pub rounds: Vec<RoundRecord>,
+    pub best_streak: u64,
+}
+
+impl RoundHistory {
+    // add a round along with the streak it left the player on
+    pub fn record(&mut self, round: RoundRecord, streak: u64) {
+        self.rounds.push(round);
+        self.best_streak = self.best_streak.max(streak);
+    }
+}
+
+// Totals over some of the rounds
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    pub round_cnt: usize,
+    // rounds cleared in the optimal count
+    pub perfect_cnt: usize,
+    pub timed_cnt: usize,
+    // only over rounds with a known optimal count
+    pub average_excess: Option<f32>,
+    pub average_secs: Option<f32>,
+}
+
+impl Summary {
+    pub fn new(rounds: &[RoundRecord]) -> Summary {
+        let excess: Vec<f32> = rounds.iter().filter_map(|round| round.excess_moves()).map(f32::from).collect();
+        let average = |total: f32, cnt: usize| (cnt > 0).then(|| total / cnt as f32);
+        Summary {
+            round_cnt: rounds.len(),
+            perfect_cnt: rounds.iter().filter(|round| round.is_perfect()).count(),
+            timed_cnt: rounds.iter().filter(|round| round.timed).count(),
+            average_excess: average(excess.iter().sum(), excess.len()),
+            average_secs: average(rounds.iter().map(|round| round.secs).sum(), rounds.len()),
+        }
+    }
+
+    // share of the rounds that were perfect, None before any are played
+    pub fn accuracy(&self) -> Option<f32> {
+        (self.round_cnt > 0).then(|| self.perfect_cnt as f32 / self.round_cnt as f32)
+    }
+}
+
+// a summary for each piece that's been played
+pub fn by_piece(rounds: &[RoundRecord]) -> Vec<(Piece, Summary)> {
+    PIECES
+        .iter()
+        .map(|&piece| {
+            let played: Vec<RoundRecord> = rounds.iter().filter(|round| round.piece == piece).cloned().collect();
+            (piece, Summary::new(&played))
+        })
+        .filter(|(_, summary)| summary.round_cnt > 0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_stats() {
+        let round = |piece, optimal_moves, moves| RoundRecord {
+            piece,
+            star_cnt: 4,
+            optimal_moves,
+            moves,
+            secs: 10.0,
+            timed: piece == Piece::Knight,
+            undone: false,
+            attempt: 1,
+        };
+        let mut history = RoundHistory::default();
+        history.record(round(Piece::Queen, Some(3), 3), 1);
+        history.record(round(Piece::Queen, Some(3), 3), 2);
+        history.record(round(Piece::Knight, Some(4), 7), 0);
+        // unsolvable boards count as rounds but not towards extra moves
+        history.record(round(Piece::Queen, None, 5), 0);
+        assert_eq!(2, history.best_streak);
+        let summary = Summary::new(&history.rounds);
+        assert_eq!((4, 2, 1), (summary.round_cnt, summary.perfect_cnt, summary.timed_cnt));
+        assert_eq!((Some(1.0), Some(10.0), Some(0.5)), (summary.average_excess, summary.average_secs, summary.accuracy()));
+        let pieces = by_piece(&history.rounds);
+        assert_eq!(vec![Piece::Queen, Piece::Knight], pieces.iter().map(|&(piece, _)| piece).collect::<Vec<_>>());
+        assert_eq!((3, Some(0.0)), (pieces[0].1.round_cnt, pieces[0].1.average_excess));
+        assert_eq!((None, None), (Summary::new(&[]).accuracy(), Summary::new(&[]).average_excess));
+    }
+
+    #[test]
+    fn test_round_score() {
+        let round = |moves, undone, attempt| RoundRecord {
+            piece: Piece::Queen,
+            star_cnt: 4,
+            optimal_moves: Some(4),
+            moves,
+            secs: 10.0,
+            timed: false,
+            undone,
+            attempt,
+        };
+        // a clean first try grows the streak
+        assert_eq!((100, 3), round(4, false, 1).score(2));
+        assert_eq!((10, 0), round(5, false, 1).score(2));
+        assert_eq!((0, 0), round(7, false, 1).score(2));
+        // taking moves back halves the points and keeps the streak where it was
+        assert_eq!((50, 2), round(4, true, 1).score(2));
+        assert_eq!((5, 0), round(5, true, 1).score(2));
+        // each retry divides them again
+        assert_eq!((33, 2), round(4, false, 3).score(2));
+        assert_eq!((25, 2), round(4, true, 2).score(2));
+        assert_eq!((2, 0), round(5, false, 4).score(2));
+        // an unsolvable board scores nothing
+        assert_eq!(
+            (0, 0),
+            RoundRecord {
+                optimal_moves: None,
+                ..round(4, false, 1)
+            }
+            .score(2)
+        );
+    }
+}